@@ -0,0 +1,130 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::disk::{DiskManager, PageId, PAGE_SIZE};
+
+/// Async front end for `DiskManager`. Every call runs the synchronous I/O on
+/// tokio's blocking thread pool, so page reads and writes never stall the
+/// runtime's worker threads. Operations are serialized on one mutex, like the
+/// `&mut self` sync API.
+#[derive(Debug, Clone)]
+pub struct AsyncDiskManager {
+    disk: Arc<Mutex<DiskManager>>,
+    timeout: Option<Duration>,
+}
+
+impl AsyncDiskManager {
+    pub fn new(disk: DiskManager) -> Self {
+        Self {
+            disk: Arc::new(Mutex::new(disk)),
+            timeout: None,
+        }
+    }
+
+    /// A call that takes longer than `timeout` fails with `TimedOut`. The
+    /// blocking I/O itself can't be cancelled and still runs to completion in
+    /// the background, so a timed-out write may or may not have landed.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub async fn read_page_data(&self, page_id: PageId) -> io::Result<Vec<u8>> {
+        self.run(move |disk| {
+            let mut data = vec![0; PAGE_SIZE];
+            disk.read_page_data(page_id, &mut data)?;
+            Ok(data)
+        })
+        .await
+    }
+
+    pub async fn write_page_data(&self, page_id: PageId, data: Vec<u8>) -> io::Result<()> {
+        self.run(move |disk| disk.write_page_data(page_id, &data))
+            .await
+    }
+
+    pub async fn allocate_page(&self) -> io::Result<PageId> {
+        self.run(|disk| disk.allocate_page()).await
+    }
+
+    pub async fn sync(&self) -> io::Result<()> {
+        self.run(|disk| disk.sync()).await
+    }
+
+    async fn run<T, F>(&self, f: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DiskManager) -> io::Result<T> + Send + 'static,
+    {
+        let disk = Arc::clone(&self.disk);
+        let task = tokio::task::spawn_blocking(move || {
+            let mut disk = disk.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            f(&mut disk)
+        });
+        let joined = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, task)
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "page I/O timed out"))?,
+            None => task.await,
+        };
+        joined.map_err(io::Error::other)?
+    }
+}
+
+#[cfg(test)]
+mod test_async_disk_manager {
+    use std::{
+        fs::remove_file,
+        io::ErrorKind,
+        sync::{mpsc, Arc},
+        thread,
+        time::Duration,
+    };
+
+    use crate::disk::{DiskManager, PAGE_SIZE};
+
+    use super::AsyncDiskManager;
+
+    #[tokio::test]
+    async fn test_write_read() {
+        let file_name = "test_async_disk_manager_write_read.txt";
+        let disk = AsyncDiskManager::new(DiskManager::open(file_name).unwrap());
+        let data: Vec<u8> = (0..PAGE_SIZE).map(|i| (i % 251) as u8).collect();
+
+        let page_id = disk.allocate_page().await.unwrap();
+        disk.write_page_data(page_id, data.clone()).await.unwrap();
+        disk.sync().await.unwrap();
+
+        assert_eq!(disk.read_page_data(page_id).await.unwrap(), data);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let file_name = "test_async_disk_manager_timeout.txt";
+        let mut disk = AsyncDiskManager::new(DiskManager::open(file_name).unwrap());
+        disk.set_timeout(Some(Duration::from_millis(10)));
+        let page_id = disk.allocate_page().await.unwrap();
+
+        // hold the lock so the read can't start before the deadline
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let lock = Arc::clone(&disk.disk);
+        let holder = thread::spawn(move || {
+            let _guard = lock.lock().unwrap();
+            locked_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        });
+        locked_rx.recv().unwrap();
+        let err = disk.read_page_data(page_id).await.unwrap_err();
+        release_tx.send(()).unwrap();
+        holder.join().unwrap();
+
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+        remove_file(file_name).unwrap();
+    }
+}
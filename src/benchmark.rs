@@ -0,0 +1,173 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::disk::{Backend, DiskManager, PageId, PAGE_SIZE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchResult {
+    pub ops: u64,
+    pub duration: Duration,
+    pub bytes: u64,
+}
+
+impl BenchResult {
+    pub fn ops_per_sec(&self) -> f64 {
+        self.ops as f64 / self.duration.as_secs_f64()
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.duration.as_secs_f64()
+    }
+}
+
+/// Appends `pages` pages and syncs once at the end, which is included in the
+/// timing.
+pub fn sequential_write_throughput<B: Backend>(
+    disk: &mut DiskManager<B>,
+    pages: u64,
+) -> io::Result<BenchResult> {
+    let data = pattern_page(disk.page_count());
+    let start = Instant::now();
+    for _ in 0..pages {
+        disk.append_page(&data)?;
+    }
+    disk.sync()?;
+    Ok(finish(start, pages))
+}
+
+/// Reads `pages` pages picked uniformly from the existing ones. Uses a fixed
+/// seed so runs against the same heap are comparable.
+pub fn random_read_iops<B: Backend>(
+    disk: &mut DiskManager<B>,
+    pages: u64,
+) -> io::Result<BenchResult> {
+    let page_count = check_not_empty(disk)?;
+    let mut rng = XorShift::new();
+    let mut data = vec![0; PAGE_SIZE];
+    let start = Instant::now();
+    for _ in 0..pages {
+        disk.read_page_data(PageId(rng.next() % page_count), &mut data)?;
+    }
+    Ok(finish(start, pages))
+}
+
+/// `ops` random page accesses over the existing pages, `write_percent` of
+/// which overwrite the page instead of reading it. Writes are synced once at
+/// the end. A `write_percent` over 100 fails with `InvalidInput`.
+pub fn mixed_workload<B: Backend>(
+    disk: &mut DiskManager<B>,
+    ops: u64,
+    write_percent: u32,
+) -> io::Result<BenchResult> {
+    if write_percent > 100 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("write_percent must be at most 100, got {write_percent}"),
+        ));
+    }
+    let page_count = check_not_empty(disk)?;
+    let mut rng = XorShift::new();
+    let mut data = vec![0; PAGE_SIZE];
+    let start = Instant::now();
+    for _ in 0..ops {
+        let page_id = PageId(rng.next() % page_count);
+        if rng.next() % 100 < write_percent as u64 {
+            data.fill(page_id.to_u64() as u8);
+            disk.write_page_data(page_id, &data)?;
+        } else {
+            disk.read_page_data(page_id, &mut data)?;
+        }
+    }
+    if write_percent > 0 {
+        disk.sync()?;
+    }
+    Ok(finish(start, ops))
+}
+
+fn finish(start: Instant, ops: u64) -> BenchResult {
+    BenchResult {
+        ops,
+        duration: start.elapsed(),
+        bytes: ops * PAGE_SIZE as u64,
+    }
+}
+
+fn check_not_empty<B: Backend>(disk: &DiskManager<B>) -> io::Result<u64> {
+    match disk.page_count() {
+        0 => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "benchmark needs a heap with at least one page",
+        )),
+        page_count => Ok(page_count),
+    }
+}
+
+// Non-zero bytes, so filesystems can't short-cut the writes as holes.
+fn pattern_page(seed: u64) -> Vec<u8> {
+    (0..PAGE_SIZE)
+        .map(|i| (i as u64 ^ seed) as u8 | 1)
+        .collect()
+}
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn new() -> Self {
+        Self(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test_benchmark {
+    use std::{io::ErrorKind, time::Duration};
+
+    use crate::disk::{DiskManager, PAGE_SIZE};
+
+    use super::{mixed_workload, random_read_iops, sequential_write_throughput};
+
+    #[test]
+    fn test_benchmarks() {
+        let mut disk = DiskManager::open_temp().unwrap();
+
+        let write = sequential_write_throughput(&mut disk, 8).unwrap();
+        let read = random_read_iops(&mut disk, 16).unwrap();
+        let mixed = mixed_workload(&mut disk, 16, 50).unwrap();
+
+        assert_eq!(disk.page_count(), 8);
+        for (result, ops) in [(write, 8), (read, 16), (mixed, 16)] {
+            assert_eq!(result.ops, ops);
+            assert_eq!(result.bytes, ops * PAGE_SIZE as u64);
+            assert!(result.duration > Duration::ZERO);
+            assert!(result.ops_per_sec() > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_benchmark_empty_heap() {
+        let mut disk = DiskManager::open_temp().unwrap();
+
+        let err = random_read_iops(&mut disk, 1).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let err = mixed_workload(&mut disk, 1, 10).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_mixed_workload_invalid_write_percent() {
+        let mut disk = DiskManager::open_temp().unwrap();
+        sequential_write_throughput(&mut disk, 1).unwrap();
+
+        let err = mixed_workload(&mut disk, 1, 101).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -1,8 +1,10 @@
 use std::{
+    alloc::{self, Layout},
     cell::{Cell, RefCell},
-    collections::HashMap,
-    io,
-    ops::{Index, IndexMut},
+    collections::{HashMap, HashSet},
+    fmt, io, mem,
+    ops::{Deref, DerefMut, Index, IndexMut},
+    ptr::NonNull,
     rc::Rc,
 };
 
@@ -21,26 +23,199 @@ pub struct BufferId(usize);
 
 pub type Page = [u8; PAGE_SIZE];
 
+/// A heap-allocated page. Aligned pages start on a PAGE_SIZE boundary, which
+/// O_DIRECT reads and writes require.
+pub struct PageBuf {
+    ptr: NonNull<Page>,
+    aligned: bool,
+}
+
+impl PageBuf {
+    pub fn zeroed(aligned: bool) -> Self {
+        let layout = Self::layout(aligned);
+        // SAFETY: the layout has a non-zero size.
+        let ptr = unsafe { alloc::alloc_zeroed(layout) } as *mut Page;
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        Self { ptr, aligned }
+    }
+
+    pub fn is_aligned(&self) -> bool {
+        self.aligned
+    }
+
+    fn layout(aligned: bool) -> Layout {
+        let align = if aligned {
+            PAGE_SIZE
+        } else {
+            mem::align_of::<Page>()
+        };
+        Layout::from_size_align(PAGE_SIZE, align).unwrap()
+    }
+}
+
+impl Deref for PageBuf {
+    type Target = Page;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: `ptr` points to a live, initialized page owned by `self`.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl DerefMut for PageBuf {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: `ptr` points to a live, initialized page owned by `self`.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl Drop for PageBuf {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` was allocated in `zeroed` with this same layout.
+        unsafe { alloc::dealloc(self.ptr.as_ptr() as *mut u8, Self::layout(self.aligned)) }
+    }
+}
+
+impl fmt::Debug for PageBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PageBuf")
+            .field("aligned", &self.aligned)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for PageBuf {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for PageBuf {}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Buffer {
     pub page_id: PageId,
-    pub page: RefCell<Page>,
+    pub page: RefCell<PageBuf>,
     pub is_dirty: Cell<bool>,
 }
 
-impl Default for Buffer {
-    fn default() -> Self {
+impl Buffer {
+    pub fn new(aligned: bool) -> Self {
         Self {
             page_id: Default::default(),
-            page: RefCell::new([0u8; PAGE_SIZE]),
+            page: RefCell::new(PageBuf::zeroed(aligned)),
             is_dirty: Cell::new(false),
         }
     }
 }
 
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+/// Reusable scratch pages for callers that read pages outside the buffer
+/// pool, so tight loops don't allocate a fresh page per read.
+#[derive(Debug, Default)]
+pub struct PageBufferPool {
+    free: RefCell<Vec<Box<Page>>>,
+}
+
+impl PageBufferPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn acquire(&self) -> PageBuffer<'_> {
+        let page = match self.free.borrow_mut().pop() {
+            Some(mut page) => {
+                page.fill(0);
+                page
+            }
+            None => Box::new([0u8; PAGE_SIZE]),
+        };
+        PageBuffer {
+            pool: self,
+            page: Some(page),
+        }
+    }
+
+    pub fn available(&self) -> usize {
+        self.free.borrow().len()
+    }
+
+    fn release(&self, page: Box<Page>) {
+        self.free.borrow_mut().push(page);
+    }
+}
+
+#[derive(Debug)]
+pub struct PageBuffer<'a> {
+    pool: &'a PageBufferPool,
+    page: Option<Box<Page>>,
+}
+
+impl Deref for PageBuffer<'_> {
+    type Target = Page;
+
+    fn deref(&self) -> &Self::Target {
+        self.page.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PageBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.page.as_mut().unwrap()
+    }
+}
+
+impl Drop for PageBuffer<'_> {
+    fn drop(&mut self) {
+        if let Some(page) = self.page.take() {
+            self.pool.release(page);
+        }
+    }
+}
+
+/// Holds pinned buffers for multi-page operations; every buffer is unpinned
+/// when the set is dropped, including on early return.
+#[derive(Debug, Default)]
+pub struct PinSet {
+    buffers: Vec<Rc<Buffer>>,
+}
+
+impl PinSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, buffer: Rc<Buffer>) -> Rc<Buffer> {
+        self.buffers.push(Rc::clone(&buffer));
+        buffer
+    }
+
+    pub fn last(&self) -> Option<&Rc<Buffer>> {
+        self.buffers.last()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    pub fn release(&mut self) {
+        self.buffers.clear();
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct Frame {
     usage_count: u64,
+    loaded_at: u64,
     buffer: Rc<Buffer>,
 }
 
@@ -48,33 +223,62 @@ pub struct Frame {
 pub struct BufferPool {
     buffers: Vec<Frame>,
     next_victim_id: BufferId,
+    prefer_clean: bool,
 }
 
 impl BufferPool {
     pub fn new(pool_size: usize) -> Self {
+        Self::with_alignment(pool_size, false)
+    }
+
+    /// With `aligned`, every frame's page starts on a PAGE_SIZE boundary so the
+    /// pool can sit on top of a heap file opened with O_DIRECT.
+    pub fn with_alignment(pool_size: usize, aligned: bool) -> Self {
         let mut buffers = vec![];
-        buffers.resize_with(pool_size, Default::default);
+        buffers.resize_with(pool_size, || Frame {
+            usage_count: 0,
+            loaded_at: 0,
+            buffer: Rc::new(Buffer::new(aligned)),
+        });
         let next_victim_id = BufferId::default();
         Self {
             buffers,
             next_victim_id,
+            prefer_clean: false,
         }
     }
 
+    /// When the clock hand lands on a dirty victim, take an equally cold clean
+    /// frame from elsewhere in the pool instead, if there is one, to save the
+    /// write-back.
+    pub fn set_prefer_clean(&mut self, prefer_clean: bool) {
+        self.prefer_clean = prefer_clean;
+    }
+
     fn size(&self) -> usize {
         self.buffers.len()
     }
 
     fn evict(&mut self) -> Option<BufferId> {
+        self.evict_except(|_| false)
+    }
+
+    // Like `evict`, but frames holding a page for which `protected` returns
+    // true are passed over as if they were pinned.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(name = "evict", level = "trace", skip_all)
+    )]
+    fn evict_except(&mut self, protected: impl Fn(PageId) -> bool) -> Option<BufferId> {
         let pool_size = self.size();
         let mut consecutive_pinned = 0;
         let victim_id = loop {
             let next_victim_id = self.next_victim_id;
             let frame = &mut self[next_victim_id];
-            if frame.usage_count == 0 {
-                break self.next_victim_id;
-            }
-            if Rc::get_mut(&mut frame.buffer).is_some() {
+            if !protected(frame.buffer.page_id) && Rc::get_mut(&mut frame.buffer).is_some() {
+                if frame.usage_count == 0 {
+                    break self.next_victim_id;
+                }
                 frame.usage_count -= 1;
                 consecutive_pinned = 0;
             } else {
@@ -85,9 +289,37 @@ impl BufferPool {
             }
             self.next_victim_id = self.increment_id(self.next_victim_id);
         };
+        if self.prefer_clean && self[victim_id].buffer.is_dirty.get() {
+            if let Some(clean_id) = self.find_cold_clean(victim_id, protected) {
+                self.next_victim_id = clean_id;
+                return Some(clean_id);
+            }
+        }
         Some(victim_id)
     }
 
+    // Looks one revolution past `start` for an unpinned, clean frame with no
+    // usage left, without aging anything on the way.
+    fn find_cold_clean(
+        &mut self,
+        start: BufferId,
+        protected: impl Fn(PageId) -> bool,
+    ) -> Option<BufferId> {
+        let mut buffer_id = self.increment_id(start);
+        while buffer_id != start {
+            let frame = &mut self[buffer_id];
+            if frame.usage_count == 0
+                && !frame.buffer.is_dirty.get()
+                && !protected(frame.buffer.page_id)
+                && Rc::get_mut(&mut frame.buffer).is_some()
+            {
+                return Some(buffer_id);
+            }
+            buffer_id = self.increment_id(buffer_id);
+        }
+        None
+    }
+
     fn increment_id(&self, buffer_id: BufferId) -> BufferId {
         BufferId((buffer_id.0 + 1) % self.size())
     }
@@ -107,10 +339,23 @@ impl IndexMut<BufferId> for BufferPool {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessHint {
+    /// Counts as one more use, e.g. for an index root.
+    WillReuse,
+    /// Forgets all uses so the frame is the next victim once released.
+    NoReuse,
+    /// Caps the frame at a single use, so pages of a long pass can't outrank
+    /// the working set however often the pass touches them.
+    Sequential,
+}
+
 pub struct BufferPoolManager {
     disk: DiskManager,
     pool: BufferPool,
     page_table: HashMap<PageId, BufferId>,
+    max_dirty_pages: Option<usize>,
+    load_count: u64,
 }
 
 impl BufferPoolManager {
@@ -120,37 +365,101 @@ impl BufferPoolManager {
             disk,
             pool,
             page_table,
+            max_dirty_pages: None,
+            load_count: 0,
         }
     }
 
+    /// Once this many frames are dirty, loading another page first writes back
+    /// the dirty pages that were loaded longest ago. Pinned pages are never
+    /// written back early, since their holder may still be changing them, so
+    /// the limit can be exceeded while many dirty pages are pinned.
+    pub fn set_max_dirty_pages(&mut self, max_dirty_pages: Option<usize>) {
+        assert_ne!(max_dirty_pages, Some(0), "max_dirty_pages must be positive");
+        self.max_dirty_pages = max_dirty_pages;
+    }
+
     pub fn fetch_page(&mut self, page_id: PageId) -> Result<Rc<Buffer>, Error> {
+        self.fetch_page_with_usage(page_id, false, &HashSet::new())
+    }
+
+    /// Never evicts a page in `protected` to make room, whether or not it is
+    /// pinned, so a multi-page operation can't push out a page it will come
+    /// back to. Fails with `NoFreeBuffer` if every other frame is pinned.
+    pub fn fetch_page_protected(
+        &mut self,
+        page_id: PageId,
+        protected: &HashSet<PageId>,
+    ) -> Result<Rc<Buffer>, Error> {
+        self.fetch_page_with_usage(page_id, false, protected)
+    }
+
+    /// For pages a sequential scan reads once: the page is not counted as a use,
+    /// so it is the first thing evicted once released and a large scan cannot
+    /// push the hot working set out of the pool.
+    pub fn fetch_page_single_use(&mut self, page_id: PageId) -> Result<Rc<Buffer>, Error> {
+        self.fetch_page_with_usage(page_id, true, &HashSet::new())
+    }
+
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "fetch_page",
+            level = "trace",
+            skip_all,
+            fields(page_id = page_id.0, single_use)
+        )
+    )]
+    fn fetch_page_with_usage(
+        &mut self,
+        page_id: PageId,
+        single_use: bool,
+        protected: &HashSet<PageId>,
+    ) -> Result<Rc<Buffer>, Error> {
         if let Some(&buffer_id) = self.page_table.get(&page_id) {
             let frame = &mut self.pool[buffer_id];
-            frame.usage_count += 1;
+            if !single_use {
+                frame.usage_count += 1;
+            }
             return Ok(Rc::clone(&frame.buffer));
         }
 
-        let buffer_id = self.pool.evict().ok_or(Error::NoFreeBuffer)?;
+        self.write_back_excess_dirty()?;
+        let buffer_id = self
+            .pool
+            .evict_except(|page_id| protected.contains(&page_id))
+            .ok_or(Error::NoFreeBuffer)?;
         let frame = &mut self.pool[buffer_id];
         let evict_page_id = frame.buffer.page_id;
         {
             let buffer = Rc::get_mut(&mut frame.buffer).unwrap();
             if buffer.is_dirty.get() {
                 self.disk
-                    .write_page_data(evict_page_id, buffer.page.get_mut())?;
+                    .write_page_data(evict_page_id, &buffer.page.get_mut()[..])?;
+                buffer.is_dirty.set(false);
+            }
+            self.page_table.remove(&evict_page_id);
+            if let Err(err) = self
+                .disk
+                .read_page_data(page_id, &mut buffer.page.get_mut()[..])
+            {
+                // The old page is gone and the new one never arrived, so the
+                // frame is left empty.
+                buffer.page_id = Default::default();
+                return Err(err.into());
             }
             buffer.page_id = page_id;
-            buffer.is_dirty.set(false);
-            self.disk.read_page_data(page_id, buffer.page.get_mut())?;
-            frame.usage_count = 1;
+            frame.usage_count = if single_use { 0 } else { 1 };
+            self.load_count += 1;
+            frame.loaded_at = self.load_count;
         }
         let page = Rc::clone(&frame.buffer);
-        self.page_table.remove(&evict_page_id);
         self.page_table.insert(page_id, buffer_id);
         Ok(page)
     }
 
     pub fn create_page(&mut self) -> Result<Rc<Buffer>, Error> {
+        self.write_back_excess_dirty()?;
         let buffer_id = self.pool.evict().ok_or(Error::NoFreeBuffer)?;
         let frame = &mut self.pool[buffer_id];
         let evict_page_id = frame.buffer.page_id;
@@ -158,13 +467,15 @@ impl BufferPoolManager {
             let buffer = Rc::get_mut(&mut frame.buffer).unwrap();
             if buffer.is_dirty.get() {
                 self.disk
-                    .write_page_data(evict_page_id, buffer.page.get_mut())?;
+                    .write_page_data(evict_page_id, &buffer.page.get_mut()[..])?;
             }
-            let page_id = self.disk.allocate_page();
-            *buffer = Buffer::default();
+            let page_id = self.disk.allocate_page()?;
+            buffer.page.get_mut().fill(0);
             buffer.page_id = page_id;
             buffer.is_dirty.set(true);
             frame.usage_count = 1;
+            self.load_count += 1;
+            frame.loaded_at = self.load_count;
             page_id
         };
         let page = Rc::clone(&frame.buffer);
@@ -173,14 +484,102 @@ impl BufferPoolManager {
         Ok(page)
     }
 
+    /// Preloads pages without keeping them pinned, so the first real fetches
+    /// after startup are hits.
+    pub fn warm(&mut self, page_ids: &[PageId]) -> Result<(), Error> {
+        for &page_id in page_ids {
+            if !self.page_table.contains_key(&page_id) {
+                self.fetch_page(page_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adjusts the eviction priority of a resident page. Pages that aren't in
+    /// the pool are ignored.
+    pub fn hint(&mut self, page_id: PageId, hint: AccessHint) {
+        let Some(&buffer_id) = self.page_table.get(&page_id) else {
+            return;
+        };
+        let frame = &mut self.pool[buffer_id];
+        frame.usage_count = match hint {
+            AccessHint::WillReuse => frame.usage_count + 1,
+            AccessHint::NoReuse => 0,
+            AccessHint::Sequential => frame.usage_count.min(1),
+        };
+    }
+
+    pub fn record_hot_set(&self) -> Vec<PageId> {
+        let mut page_ids: Vec<PageId> = self.page_table.keys().copied().collect();
+        page_ids.sort_by_key(|page_id| page_id.to_u64());
+        page_ids
+    }
+
+    pub fn dirty_pages(&self) -> Vec<PageId> {
+        let mut page_ids: Vec<PageId> = self
+            .page_table
+            .iter()
+            .filter(|&(_, &buffer_id)| self.pool[buffer_id].buffer.is_dirty.get())
+            .map(|(&page_id, _)| page_id)
+            .collect();
+        page_ids.sort_by_key(|page_id| page_id.to_u64());
+        page_ids
+    }
+
+    /// Pages currently held by callers, with how many handles each has out.
+    pub fn pinned_pages(&self) -> Vec<(PageId, u32)> {
+        let mut pinned: Vec<(PageId, u32)> = self
+            .page_table
+            .iter()
+            .filter_map(|(&page_id, &buffer_id)| {
+                let pin_count = Rc::strong_count(&self.pool[buffer_id].buffer) - 1;
+                (pin_count > 0).then_some((page_id, pin_count as u32))
+            })
+            .collect();
+        pinned.sort_by_key(|(page_id, _)| page_id.to_u64());
+        pinned
+    }
+
+    // Makes room for one more dirty page under `max_dirty_pages`. Pinned pages
+    // are skipped, as they are by eviction.
+    fn write_back_excess_dirty(&mut self) -> Result<(), Error> {
+        let Some(max_dirty_pages) = self.max_dirty_pages else {
+            return Ok(());
+        };
+        let mut dirty_frames: Vec<&Frame> = self
+            .pool
+            .buffers
+            .iter()
+            .filter(|frame| frame.buffer.is_dirty.get())
+            .collect();
+        if dirty_frames.len() < max_dirty_pages {
+            return Ok(());
+        }
+        dirty_frames.sort_by_key(|frame| frame.loaded_at);
+        let excess = dirty_frames.len() + 1 - max_dirty_pages;
+        let unpinned_frames = dirty_frames
+            .into_iter()
+            .filter(|frame| Rc::strong_count(&frame.buffer) == 1);
+        for frame in unpinned_frames.take(excess) {
+            let page = frame.buffer.page.borrow();
+            self.disk.write_page_data(frame.buffer.page_id, &page[..])?;
+            frame.buffer.is_dirty.set(false);
+        }
+        Ok(())
+    }
+
     pub fn flush(&mut self) -> Result<(), Error> {
         for (&page_id, &buffer_id) in self.page_table.iter() {
             let frame = &self.pool[buffer_id];
-            let mut page = frame.buffer.page.borrow_mut();
-            self.disk.write_page_data(page_id, page.as_mut())?;
-            frame.buffer.is_dirty.set(false);
+            let page = frame.buffer.page.borrow();
+            self.disk.write_page_data(page_id, &page[..])?;
         }
         self.disk.sync()?;
+        // Only now are the pages known to be on disk; if any write or the sync
+        // failed they stay dirty and the next flush retries them.
+        for &buffer_id in self.page_table.values() {
+            self.pool[buffer_id].buffer.is_dirty.set(false);
+        }
         Ok(())
     }
 }
@@ -191,7 +590,7 @@ mod test_buffer {
 
     use crate::disk::PAGE_SIZE;
 
-    use super::Buffer;
+    use super::{Buffer, PageBuf};
 
     #[test]
     fn test_default() {
@@ -199,16 +598,29 @@ mod test_buffer {
             Buffer::default(),
             Buffer {
                 page_id: Default::default(),
-                page: RefCell::new([0u8; PAGE_SIZE]),
+                page: RefCell::new(PageBuf::zeroed(false)),
                 is_dirty: Cell::new(false),
             }
         );
+        assert_eq!(**Buffer::default().page.borrow(), [0u8; PAGE_SIZE]);
+    }
+
+    #[test]
+    fn test_new_aligned() {
+        let buffer = Buffer::new(true);
+        let page = buffer.page.borrow();
+
+        assert!(page.is_aligned());
+        assert_eq!(page.as_ptr() as usize % PAGE_SIZE, 0);
+        assert_eq!(**page, [0u8; PAGE_SIZE]);
     }
 }
 
 #[cfg(test)]
 mod test_buffer_pool {
-    use crate::buffer::BufferId;
+    use std::rc::Rc;
+
+    use crate::{buffer::BufferId, disk::PAGE_SIZE};
 
     use super::BufferPool;
 
@@ -218,7 +630,8 @@ mod test_buffer_pool {
             BufferPool::new(5),
             BufferPool {
                 buffers: vec![Default::default(); 5],
-                next_victim_id: BufferId::default()
+                next_victim_id: BufferId::default(),
+                prefer_clean: false,
             }
         );
     }
@@ -228,4 +641,655 @@ mod test_buffer_pool {
         let buffer = BufferPool::new(5);
         assert_eq!(buffer.size(), 5);
     }
+
+    #[test]
+    fn test_with_alignment() {
+        let pool = BufferPool::with_alignment(3, true);
+
+        assert_eq!(pool.size(), 3);
+        for frame in &pool.buffers {
+            let page = frame.buffer.page.borrow();
+            assert!(page.is_aligned());
+            assert_eq!(page.as_ptr() as usize % PAGE_SIZE, 0);
+        }
+    }
+
+    #[test]
+    fn test_evict_prefer_clean() {
+        for (prefer_clean, expected) in [(false, BufferId(1)), (true, BufferId(2))] {
+            let mut pool = BufferPool::new(4);
+            pool.set_prefer_clean(prefer_clean);
+            pool.buffers[0].usage_count = 1;
+            pool.buffers[1].buffer.is_dirty.set(true);
+            let _pinned = Rc::clone(&pool.buffers[3].buffer);
+            pool.next_victim_id = BufferId(1);
+
+            assert_eq!(pool.evict(), Some(expected));
+            assert_eq!(pool.next_victim_id, expected);
+            // nothing was aged while looking for a clean frame
+            assert_eq!(pool.buffers[0].usage_count, 1);
+        }
+    }
+
+    #[test]
+    fn test_evict_prefer_clean_all_dirty() {
+        let mut pool = BufferPool::new(2);
+        pool.set_prefer_clean(true);
+        for frame in &pool.buffers {
+            frame.buffer.is_dirty.set(true);
+        }
+
+        assert_eq!(pool.evict(), Some(BufferId(0)));
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test_buffer_pool_manager_direct_io {
+    use std::{
+        fs::{remove_file, OpenOptions},
+        io::ErrorKind,
+        os::unix::fs::OpenOptionsExt,
+    };
+
+    use crate::disk::{DiskManager, PageId, PAGE_SIZE};
+
+    use super::{BufferPool, BufferPoolManager};
+
+    #[test]
+    fn test_aligned_o_direct() {
+        let file_name = "test_buffer_pool_manager_aligned_o_direct.txt";
+        let open_direct = || {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .custom_flags(libc::O_DIRECT)
+                .open(file_name)
+        };
+        let heap_file = match open_direct() {
+            Ok(heap_file) => heap_file,
+            // tmpfs and some overlayfs setups reject O_DIRECT with EINVAL
+            Err(err) if err.kind() == ErrorKind::InvalidInput => {
+                let _ = remove_file(file_name);
+                eprintln!("skipping test_aligned_o_direct: O_DIRECT is not supported here");
+                return;
+            }
+            Err(err) => panic!("{err}"),
+        };
+
+        let mut bpm = BufferPoolManager::new(
+            DiskManager::new(heap_file).unwrap(),
+            BufferPool::with_alignment(1, true),
+        );
+        let buffer = bpm.create_page().unwrap();
+        buffer.page.borrow_mut()[..13].copy_from_slice(b"Hello, World!");
+        drop(buffer);
+        bpm.flush().unwrap();
+        drop(bpm);
+
+        let mut bpm = BufferPoolManager::new(
+            DiskManager::new(open_direct().unwrap()).unwrap(),
+            BufferPool::with_alignment(1, true),
+        );
+        let buffer = bpm.fetch_page(PageId(0)).unwrap();
+        let page = buffer.page.borrow();
+
+        assert_eq!(&page[..13], b"Hello, World!");
+        assert!(page[13..PAGE_SIZE].iter().all(|&b| b == 0));
+
+        drop(page);
+        remove_file(file_name).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_buffer_pool_manager {
+    use std::{
+        collections::HashSet,
+        fs::{remove_file, OpenOptions},
+        rc::Rc,
+    };
+
+    use crate::disk::{DiskManager, PageId, PAGE_SIZE};
+
+    use super::{AccessHint, BufferPool, BufferPoolManager, Error};
+
+    #[test]
+    fn test_max_dirty_pages() {
+        let file_name = "test_buffer_pool_manager_max_dirty_pages.txt";
+        let disk = DiskManager::open(file_name).unwrap();
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(8));
+        bpm.set_max_dirty_pages(Some(2));
+
+        for i in 0..10 {
+            let buffer = bpm.create_page().unwrap();
+            buffer.page.borrow_mut().fill(i as u8);
+            drop(buffer);
+
+            assert!(bpm.dirty_pages().len() <= 2);
+        }
+        bpm.flush().unwrap();
+        drop(bpm);
+
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..10 {
+            assert_eq!(
+                disk.read_into_vec(PageId(i)).unwrap(),
+                vec![i as u8; PAGE_SIZE]
+            );
+        }
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_max_dirty_pages_skips_pinned() {
+        let file_name = "test_buffer_pool_manager_max_dirty_pages_skips_pinned.txt";
+        let disk = DiskManager::open(file_name).unwrap();
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(8));
+        bpm.set_max_dirty_pages(Some(1));
+
+        let pinned = bpm.create_page().unwrap();
+        pinned.page.borrow_mut().fill(1);
+        bpm.create_page().unwrap();
+
+        assert_eq!(bpm.dirty_pages(), vec![PageId(0), PageId(1)]);
+        pinned.page.borrow_mut().fill(2);
+        drop(pinned);
+        bpm.create_page().unwrap();
+
+        assert_eq!(bpm.dirty_pages(), vec![PageId(2)]);
+        drop(bpm);
+        let mut disk = DiskManager::open(file_name).unwrap();
+        assert_eq!(disk.read_into_vec(PageId(0)).unwrap(), vec![2; PAGE_SIZE]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_max_dirty_pages_writes_back_oldest() {
+        let file_name = "test_buffer_pool_manager_max_dirty_pages_writes_back_oldest.txt";
+        let disk = DiskManager::open(file_name).unwrap();
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(8));
+        bpm.set_max_dirty_pages(Some(3));
+
+        for _ in 0..4 {
+            bpm.create_page().unwrap();
+        }
+
+        assert_eq!(bpm.dirty_pages(), vec![PageId(1), PageId(2), PageId(3)]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_warm() {
+        let file_name = "test_buffer_pool_manager_warm.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..4 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(3));
+
+        bpm.warm(&[PageId(1), PageId(3)]).unwrap();
+
+        assert_eq!(bpm.record_hot_set(), vec![PageId(1), PageId(3)]);
+        assert!(bpm
+            .pool
+            .buffers
+            .iter()
+            .all(|frame| Rc::strong_count(&frame.buffer) == 1));
+
+        // Change the file behind the pool's back: hits still see the old bytes.
+        let mut disk = DiskManager::open_unlocked(file_name).unwrap();
+        disk.write_page_data(PageId(1), &[9; PAGE_SIZE]).unwrap();
+        disk.write_page_data(PageId(3), &[9; PAGE_SIZE]).unwrap();
+        disk.write_page_data(PageId(2), &[9; PAGE_SIZE]).unwrap();
+
+        let buffer = bpm.fetch_page(PageId(1)).unwrap();
+        assert_eq!(**buffer.page.borrow(), [1; PAGE_SIZE]);
+        let buffer = bpm.fetch_page(PageId(3)).unwrap();
+        assert_eq!(**buffer.page.borrow(), [3; PAGE_SIZE]);
+        let buffer = bpm.fetch_page(PageId(2)).unwrap();
+        assert_eq!(**buffer.page.borrow(), [9; PAGE_SIZE]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_flush_failure_keeps_dirty() {
+        let file_name = "test_buffer_pool_manager_flush_failure_keeps_dirty.txt";
+        DiskManager::open(file_name)
+            .unwrap()
+            .append_page(&[1; PAGE_SIZE])
+            .unwrap();
+        let read_only_file = OpenOptions::new().read(true).open(file_name).unwrap();
+        let mut bpm = BufferPoolManager::new(
+            DiskManager::new(read_only_file).unwrap(),
+            BufferPool::new(2),
+        );
+
+        let buffer = bpm.fetch_page(PageId(0)).unwrap();
+        buffer.page.borrow_mut().fill(2);
+        buffer.is_dirty.set(true);
+
+        assert!(matches!(bpm.flush(), Err(Error::Io(_))));
+        assert!(buffer.is_dirty.get());
+
+        bpm.disk = DiskManager::open(file_name).unwrap();
+        bpm.flush().unwrap();
+
+        assert!(!buffer.is_dirty.get());
+        assert_eq!(
+            bpm.disk.read_into_vec(PageId(0)).unwrap(),
+            vec![2; PAGE_SIZE]
+        );
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_evict_failure_keeps_dirty() {
+        let file_name = "test_buffer_pool_manager_evict_failure_keeps_dirty.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        disk.append_page(&[1; PAGE_SIZE]).unwrap();
+        disk.append_page(&[1; PAGE_SIZE]).unwrap();
+        let read_only_file = OpenOptions::new().read(true).open(file_name).unwrap();
+        let mut bpm = BufferPoolManager::new(
+            DiskManager::new(read_only_file).unwrap(),
+            BufferPool::new(1),
+        );
+
+        let buffer = bpm.fetch_page(PageId(0)).unwrap();
+        buffer.page.borrow_mut().fill(2);
+        buffer.is_dirty.set(true);
+        drop(buffer);
+
+        assert!(matches!(bpm.fetch_page(PageId(1)), Err(Error::Io(_))));
+
+        let buffer = bpm.fetch_page(PageId(0)).unwrap();
+        assert!(buffer.is_dirty.get());
+        assert_eq!(**buffer.page.borrow(), [2; PAGE_SIZE]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_read_failure_empties_frame() {
+        let file_name = "test_buffer_pool_manager_read_failure_empties_frame.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        disk.append_page(&[1; PAGE_SIZE]).unwrap();
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(1));
+
+        bpm.fetch_page(PageId(0)).unwrap();
+
+        assert!(matches!(bpm.fetch_page(PageId(5)), Err(Error::Io(_))));
+        assert!(bpm.record_hot_set().is_empty());
+
+        let buffer = bpm.fetch_page(PageId(0)).unwrap();
+        assert_eq!(buffer.page_id, PageId(0));
+        assert_eq!(**buffer.page.borrow(), [1; PAGE_SIZE]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_fetch_page_single_use_keeps_hot_page() {
+        let file_name = "test_buffer_pool_manager_fetch_page_single_use_keeps_hot_page.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..20 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(3));
+        bpm.fetch_page(PageId(0)).unwrap();
+
+        for i in 1..20 {
+            let buffer = bpm.fetch_page_single_use(PageId(i)).unwrap();
+            assert_eq!(**buffer.page.borrow(), [i as u8; PAGE_SIZE]);
+        }
+
+        assert!(bpm.record_hot_set().contains(&PageId(0)));
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_fetch_page_floods_hot_page() {
+        let file_name = "test_buffer_pool_manager_fetch_page_floods_hot_page.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..20 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(3));
+        bpm.fetch_page(PageId(0)).unwrap();
+
+        for i in 1..20 {
+            bpm.fetch_page(PageId(i)).unwrap();
+        }
+
+        assert!(!bpm.record_hot_set().contains(&PageId(0)));
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_hint() {
+        let file_name = "test_buffer_pool_manager_hint.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..3 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(2));
+        bpm.fetch_page(PageId(0)).unwrap();
+        bpm.fetch_page(PageId(1)).unwrap();
+
+        bpm.hint(PageId(0), AccessHint::WillReuse);
+        bpm.hint(PageId(1), AccessHint::NoReuse);
+        bpm.hint(PageId(2), AccessHint::WillReuse);
+        bpm.fetch_page(PageId(2)).unwrap();
+
+        assert_eq!(bpm.record_hot_set(), vec![PageId(0), PageId(2)]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_hint_sequential() {
+        let file_name = "test_buffer_pool_manager_hint_sequential.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..3 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(2));
+        for _ in 0..5 {
+            bpm.fetch_page(PageId(0)).unwrap();
+        }
+        bpm.fetch_page(PageId(1)).unwrap();
+        bpm.fetch_page(PageId(1)).unwrap();
+
+        bpm.hint(PageId(0), AccessHint::Sequential);
+        bpm.fetch_page(PageId(2)).unwrap();
+
+        // page 0 drops to one use, so the sweep ages it out before page 1
+        assert_eq!(bpm.record_hot_set(), vec![PageId(1), PageId(2)]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_fetch_page_protected() {
+        let file_name = "test_buffer_pool_manager_fetch_page_protected.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..20 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(3));
+        bpm.fetch_page(PageId(0)).unwrap();
+        let protected = HashSet::from([PageId(0)]);
+
+        for i in 1..20 {
+            let buffer = bpm.fetch_page_protected(PageId(i), &protected).unwrap();
+            assert_eq!(**buffer.page.borrow(), [i as u8; PAGE_SIZE]);
+        }
+
+        assert!(bpm.record_hot_set().contains(&PageId(0)));
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_fetch_page_protected_no_free_buffer() {
+        let file_name = "test_buffer_pool_manager_fetch_page_protected_no_free_buffer.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..3 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(2));
+        bpm.fetch_page(PageId(0)).unwrap();
+        let _pinned = bpm.fetch_page(PageId(1)).unwrap();
+        let protected = HashSet::from([PageId(0)]);
+
+        let result = bpm.fetch_page_protected(PageId(2), &protected);
+
+        assert!(matches!(result, Err(Error::NoFreeBuffer)));
+        assert_eq!(bpm.record_hot_set(), vec![PageId(0), PageId(1)]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_fetch_page_single_use_pinned() {
+        let file_name = "test_buffer_pool_manager_fetch_page_single_use_pinned.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..3 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(2));
+
+        let pinned = bpm.fetch_page_single_use(PageId(0)).unwrap();
+        bpm.fetch_page(PageId(1)).unwrap();
+        bpm.fetch_page(PageId(2)).unwrap();
+
+        assert_eq!(pinned.page_id, PageId(0));
+        assert_eq!(**pinned.page.borrow(), [0; PAGE_SIZE]);
+        assert_eq!(bpm.record_hot_set(), vec![PageId(0), PageId(2)]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_fetch_page_all_pinned() {
+        let file_name = "test_buffer_pool_manager_fetch_page_all_pinned.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..3 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(2));
+
+        let _first = bpm.fetch_page(PageId(0)).unwrap();
+        let _second = bpm.fetch_page(PageId(1)).unwrap();
+
+        assert!(matches!(
+            bpm.fetch_page(PageId(2)),
+            Err(Error::NoFreeBuffer)
+        ));
+        assert!(matches!(bpm.create_page(), Err(Error::NoFreeBuffer)));
+        assert_eq!(bpm.disk.page_count(), 3);
+        assert_eq!(bpm.record_hot_set(), vec![PageId(0), PageId(1)]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_dirty_pages() {
+        let file_name = "test_buffer_pool_manager_dirty_pages.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..4 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(4));
+        for i in 0..4 {
+            bpm.fetch_page(PageId(i)).unwrap();
+        }
+
+        assert!(bpm.dirty_pages().is_empty());
+
+        for i in [3, 1] {
+            let buffer = bpm.fetch_page(PageId(i)).unwrap();
+            buffer.page.borrow_mut().fill(9);
+            buffer.is_dirty.set(true);
+        }
+
+        assert_eq!(bpm.dirty_pages(), vec![PageId(1), PageId(3)]);
+
+        bpm.flush().unwrap();
+
+        assert!(bpm.dirty_pages().is_empty());
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_pinned_pages() {
+        let file_name = "test_buffer_pool_manager_pinned_pages.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        for i in 0..3 {
+            disk.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+        let mut bpm = BufferPoolManager::new(disk, BufferPool::new(3));
+
+        let _first = bpm.fetch_page(PageId(2)).unwrap();
+        let _second = bpm.fetch_page(PageId(2)).unwrap();
+        bpm.fetch_page(PageId(1)).unwrap();
+        let _third = bpm.fetch_page(PageId(0)).unwrap();
+
+        assert_eq!(bpm.pinned_pages(), vec![(PageId(0), 1), (PageId(2), 2)]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_record_hot_set_empty() {
+        let file_name = "test_buffer_pool_manager_record_hot_set_empty.txt";
+        let disk = DiskManager::open(file_name).unwrap();
+        let bpm = BufferPoolManager::new(disk, BufferPool::new(3));
+
+        assert!(bpm.record_hot_set().is_empty());
+
+        remove_file(file_name).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_page_buffer_pool {
+    use std::{
+        fs::{remove_file, OpenOptions},
+        io::Write,
+    };
+
+    use crate::disk::{DiskManager, PageId, PAGE_SIZE};
+
+    use super::PageBufferPool;
+
+    #[test]
+    fn test_acquire_new() {
+        let pool = PageBufferPool::new();
+
+        let buf = pool.acquire();
+
+        assert_eq!(*buf, [0u8; PAGE_SIZE]);
+        assert_eq!(pool.available(), 0);
+    }
+
+    #[test]
+    fn test_acquire_reuse() {
+        let file_name = "test_page_buffer_pool_acquire_reuse.txt";
+        let mut file = OpenOptions::new()
+            .write(true)
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .open(file_name)
+            .unwrap();
+        file.write_all(&[1; PAGE_SIZE]).unwrap();
+        let mut disk_manager = DiskManager::new(file).unwrap();
+        let pool = PageBufferPool::new();
+
+        let mut buf = pool.acquire();
+        disk_manager
+            .read_page_data(PageId(0), buf.as_mut())
+            .unwrap();
+        assert_eq!(*buf, [1u8; PAGE_SIZE]);
+        let ptr = buf.as_ptr();
+        drop(buf);
+
+        assert_eq!(pool.available(), 1);
+
+        let buf = pool.acquire();
+
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(*buf, [0u8; PAGE_SIZE]);
+        assert_eq!(pool.available(), 0);
+
+        remove_file(file_name).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_pin_set {
+    use std::{
+        fs::{remove_file, OpenOptions},
+        io::Write,
+        rc::Rc,
+    };
+
+    use crate::disk::{DiskManager, PageId, PAGE_SIZE};
+
+    use super::{BufferPool, BufferPoolManager, Error, PinSet};
+
+    fn descend(bpm: &mut BufferPoolManager, path: &[PageId]) -> Result<PinSet, Error> {
+        let mut pins = PinSet::new();
+        for &page_id in path {
+            pins.push(bpm.fetch_page(page_id)?);
+        }
+        Ok(pins)
+    }
+
+    #[test]
+    fn test_push() {
+        let file_name = "test_pin_set_push.txt";
+        let mut bpm = create_buffer_pool_manager(file_name, 3, 3);
+
+        let mut pins = PinSet::new();
+        let buffer = pins.push(bpm.fetch_page(PageId(0)).unwrap());
+
+        assert_eq!(pins.len(), 1);
+        assert_eq!(buffer.page_id, PageId(0));
+        assert_eq!(pins.last().unwrap().page_id, PageId(0));
+        // held by the frame, the set and `buffer`
+        assert_eq!(Rc::strong_count(&buffer), 3);
+
+        pins.release();
+
+        assert!(pins.is_empty());
+        assert_eq!(Rc::strong_count(&buffer), 2);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_unpin_on_error() {
+        let file_name = "test_pin_set_unpin_on_error.txt";
+        let mut bpm = create_buffer_pool_manager(file_name, 3, 2);
+
+        let result = descend(&mut bpm, &[PageId(0), PageId(1), PageId(2)]);
+
+        assert!(matches!(result, Err(Error::NoFreeBuffer)));
+        assert!(bpm
+            .pool
+            .buffers
+            .iter()
+            .all(|frame| Rc::strong_count(&frame.buffer) == 1));
+        assert!(descend(&mut bpm, &[PageId(2)]).is_ok());
+
+        remove_file(file_name).unwrap();
+    }
+
+    fn create_buffer_pool_manager(
+        file_name: &str,
+        page_count: usize,
+        pool_size: usize,
+    ) -> BufferPoolManager {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .open(file_name)
+            .unwrap();
+        file.write_all(&vec![0; PAGE_SIZE * page_count]).unwrap();
+        let disk = DiskManager::new(file).unwrap();
+        BufferPoolManager::new(disk, BufferPool::new(pool_size))
+    }
 }
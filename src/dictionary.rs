@@ -0,0 +1,157 @@
+use std::{collections::HashMap, io};
+
+use crate::{disk::DiskManager, framed::FramedHeapFile};
+
+pub type Code = u32;
+
+// Leads every stored value, so the empty string still makes a non-empty frame.
+const VALUE_MARKER: u8 = 1;
+
+/// Interns strings of a low-cardinality column as small integer codes. Each
+/// distinct value is appended once to a framed heap file of its own, and its
+/// code is its position in that log, so codes stay stable across reopens.
+pub struct Dictionary {
+    heap: FramedHeapFile,
+    values: Vec<String>,
+    codes: HashMap<String, Code>,
+}
+
+impl Dictionary {
+    pub fn new(disk: DiskManager) -> io::Result<Self> {
+        let mut heap = FramedHeapFile::new(disk)?;
+        let mut values = vec![];
+        let mut codes = HashMap::new();
+        for record in heap.scan() {
+            let mut record = record?;
+            if record.first() != Some(&VALUE_MARKER) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "dictionary record has no value marker",
+                ));
+            }
+            record.remove(0);
+            let value = String::from_utf8(record)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            codes.insert(value.clone(), values.len() as Code);
+            values.push(value);
+        }
+        Ok(Self {
+            heap,
+            values,
+            codes,
+        })
+    }
+
+    pub fn encode(&mut self, s: &str) -> io::Result<Code> {
+        if let Some(&code) = self.codes.get(s) {
+            return Ok(code);
+        }
+        let code = Code::try_from(self.values.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                "dictionary has run out of codes",
+            )
+        })?;
+        let mut record = Vec::with_capacity(1 + s.len());
+        record.push(VALUE_MARKER);
+        record.extend_from_slice(s.as_bytes());
+        self.heap.append(&record)?;
+        self.codes.insert(s.to_owned(), code);
+        self.values.push(s.to_owned());
+        Ok(code)
+    }
+
+    /// Returns `None` for a code this dictionary never handed out.
+    pub fn decode(&self, code: Code) -> Option<&str> {
+        self.values.get(code as usize).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn stored_bytes(&self) -> u64 {
+        self.heap.end_offset()
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.heap.sync()
+    }
+}
+
+#[cfg(test)]
+mod test_dictionary {
+    use std::fs::remove_file;
+
+    use crate::disk::DiskManager;
+
+    use super::Dictionary;
+
+    #[test]
+    fn test_encode_decode() {
+        let file_name = "test_dictionary_encode_decode.txt";
+        let mut dictionary = Dictionary::new(DiskManager::open(file_name).unwrap()).unwrap();
+        let statuses = ["active", "suspended", "deleted", "pending"];
+        let column: Vec<&str> = (0..1000).map(|i| statuses[i * 7 % 4]).collect();
+
+        let codes: Vec<u32> = column
+            .iter()
+            .map(|s| dictionary.encode(s).unwrap())
+            .collect();
+
+        assert_eq!(dictionary.len(), statuses.len());
+        assert!(codes.iter().all(|&code| code < statuses.len() as u32));
+        let decoded: Vec<&str> = codes
+            .iter()
+            .map(|&code| dictionary.decode(code).unwrap())
+            .collect();
+        assert_eq!(decoded, column);
+        assert_eq!(dictionary.decode(statuses.len() as u32), None);
+        let inline_bytes: usize = column.iter().map(|s| s.len()).sum();
+        assert!(dictionary.stored_bytes() * 100 < inline_bytes as u64);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_reopen() {
+        let file_name = "test_dictionary_reopen.txt";
+        let mut dictionary = Dictionary::new(DiskManager::open(file_name).unwrap()).unwrap();
+        let jp = dictionary.encode("Japan").unwrap();
+        let fr = dictionary.encode("France").unwrap();
+        dictionary.sync().unwrap();
+        drop(dictionary);
+
+        let mut dictionary = Dictionary::new(DiskManager::open(file_name).unwrap()).unwrap();
+
+        assert_eq!(dictionary.decode(jp), Some("Japan"));
+        assert_eq!(dictionary.decode(fr), Some("France"));
+        assert_eq!(dictionary.encode("France").unwrap(), fr);
+        assert_eq!(dictionary.encode("Brazil").unwrap(), 2);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_encode_empty() {
+        let file_name = "test_dictionary_encode_empty.txt";
+        let mut dictionary = Dictionary::new(DiskManager::open(file_name).unwrap()).unwrap();
+
+        let empty = dictionary.encode("").unwrap();
+        let x = dictionary.encode("x").unwrap();
+
+        assert_eq!(dictionary.encode("").unwrap(), empty);
+        assert_eq!(dictionary.decode(empty), Some(""));
+        drop(dictionary);
+        let dictionary = Dictionary::new(DiskManager::open(file_name).unwrap()).unwrap();
+        assert_eq!(dictionary.len(), 2);
+        assert_eq!(dictionary.decode(empty), Some(""));
+        assert_eq!(dictionary.decode(x), Some("x"));
+
+        remove_file(file_name).unwrap();
+    }
+}
@@ -1,12 +1,17 @@
 use std::{
-    fs::{File, OpenOptions},
+    fmt,
+    fs::{remove_file, File, OpenOptions, TryLockError},
     io::{self, Read, Seek, Write},
-    path::Path,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
 };
 
 pub const PAGE_SIZE: usize = 4096;
 
-#[derive(Debug, Eq, PartialEq)]
+pub const DEFAULT_MAX_IO_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct PageId(pub u64);
 impl PageId {
     pub const INVALID_PAGE_ID: PageId = PageId(u64::MAX);
@@ -43,54 +48,836 @@ impl From<&[u8]> for PageId {
     }
 }
 
-pub struct DiskManager {
-    heap_file: File,
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TornTail {
+    pub page_count: u64,
+    pub tail_len: u64,
+}
+
+/// Storage a DiskManager can keep its pages in. Only `sync` needs to know
+/// what the storage is; backends that can't be made durable just flush.
+pub trait Backend: Read + Write + Seek {
+    fn sync(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl Backend for File {
+    fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.sync_all()
+    }
+}
+
+impl Backend for io::Cursor<Vec<u8>> {}
+
+impl<B: Backend + ?Sized> Backend for Box<B> {
+    fn sync(&mut self) -> io::Result<()> {
+        (**self).sync()
+    }
+}
+
+pub struct DiskManager<B: Backend = File> {
+    heap_file: B,
+    heap_file_path: Option<PathBuf>,
     next_page_id: u64,
+    read_only: bool,
+    max_io_attempts: u32,
+    verify_writes: bool,
+    // Files split off the end of the heap, in page id order.
+    shards: Vec<Shard<B>>,
+}
+
+struct Shard<B> {
+    first_page_id: u64,
+    file: B,
 }
 
-impl DiskManager {
+impl DiskManager<File> {
     pub fn new(heap_file: File) -> io::Result<Self> {
+        Self::from_backend(heap_file)
+    }
+
+    /// Backed by an already-unlinked temporary file, so nothing is left on disk
+    /// once the manager is dropped.
+    pub fn open_temp() -> io::Result<Self> {
+        Self::new(tempfile::tempfile()?)
+    }
+
+    pub fn open_temp_in(dir: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(tempfile::tempfile_in(dir)?)
+    }
+
+    /// Takes a shared lock, so it fails while a writable manager has the file
+    /// open but any number of read-only managers can share it.
+    pub fn open_read_only(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        let heap_file_path = heap_file_path.as_ref();
+        let heap_file = OpenOptions::new()
+            .read(true)
+            .write(false)
+            .open(heap_file_path)?;
+        lock_heap_file(&heap_file, heap_file_path, true)?;
+        let mut disk = Self::new(heap_file)?;
+        disk.heap_file_path = Some(heap_file_path.to_path_buf());
+        disk.read_only = true;
+        Ok(disk)
+    }
+
+    /// Holds an exclusive advisory lock on the file until the manager is
+    /// dropped. Opening a file another manager holds, in this process or any
+    /// other, fails with `ResourceBusy`.
+    pub fn open(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_with_lock(heap_file_path, true)
+    }
+
+    /// Like `open` but ignores the lock, for recovery tools pointed at a file
+    /// whose owner is known to be wedged.
+    pub fn open_unlocked(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_with_lock(heap_file_path, false)
+    }
+
+    fn open_with_lock(heap_file_path: impl AsRef<Path>, lock: bool) -> io::Result<Self> {
+        let heap_file_path = heap_file_path.as_ref();
+        let heap_file = Self::open_heap_file(heap_file_path)?;
+        if lock {
+            lock_heap_file(&heap_file, heap_file_path, false)?;
+        }
+        let mut disk = Self::new(heap_file)?;
+        disk.heap_file_path = Some(heap_file_path.to_path_buf());
+        Ok(disk)
+    }
+
+    /// Truncates a partially written last page (e.g. left by a crash mid-write)
+    /// back to the last whole page and reports what was cut off.
+    pub fn open_checked(heap_file_path: impl AsRef<Path>) -> io::Result<(Self, Option<TornTail>)> {
+        let heap_file_path = heap_file_path.as_ref();
+        let heap_file = Self::open_heap_file(heap_file_path)?;
+        lock_heap_file(&heap_file, heap_file_path, false)?;
         let heap_file_size = heap_file.metadata()?.len();
-        let next_page_id = heap_file_size / PAGE_SIZE as u64;
-        Ok(Self {
+        let tail_len = heap_file_size % PAGE_SIZE as u64;
+        let torn_tail = if tail_len == 0 {
+            None
+        } else {
+            let page_count = heap_file_size / PAGE_SIZE as u64;
+            heap_file.set_len(page_count * PAGE_SIZE as u64)?;
+            heap_file.sync_all()?;
+            Some(TornTail {
+                page_count,
+                tail_len,
+            })
+        };
+        let mut disk = Self::new(heap_file)?;
+        disk.heap_file_path = Some(heap_file_path.to_path_buf());
+        Ok((disk, torn_tail))
+    }
+
+    /// Reopens a heap that `split_file` spread over several files, given the
+    /// original file followed by each split-off file in the order they were
+    /// split. Every file but the last must hold at least one whole page and
+    /// no partial page. Each file is locked as by `open`.
+    pub fn open_sharded(heap_file_paths: &[impl AsRef<Path>]) -> io::Result<Self> {
+        let Some((first_path, shard_paths)) = heap_file_paths.split_first() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no heap files given",
+            ));
+        };
+        let mut disk = Self::open(first_path)?;
+        for shard_path in shard_paths {
+            let shard_path = shard_path.as_ref();
+            let prev_file = disk
+                .shards
+                .last()
+                .map_or(&disk.heap_file, |shard| &shard.file);
+            let prev_len = prev_file.metadata()?.len();
+            if prev_len == 0 || !prev_len.is_multiple_of(PAGE_SIZE as u64) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "heap file before {} is {} bytes, not a whole number of pages",
+                        shard_path.display(),
+                        prev_len
+                    ),
+                ));
+            }
+            let file = OpenOptions::new().read(true).write(true).open(shard_path)?;
+            lock_heap_file(&file, shard_path, false)?;
+            let first_page_id = disk.next_page_id;
+            disk.next_page_id = first_page_id + file.metadata()?.len() / PAGE_SIZE as u64;
+            disk.shards.push(Shard {
+                first_page_id,
+                file,
+            });
+        }
+        Ok(disk)
+    }
+
+    /// Opens a second, read-only handle with its own cursor so readers don't
+    /// move the writer's seek position. Only available for managers opened by
+    /// path.
+    pub fn open_reader(&self) -> io::Result<DiskReader> {
+        if !self.shards.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "disk manager spans several files",
+            ));
+        }
+        let heap_file_path = self.heap_file_path.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "disk manager was not opened from a path",
+            )
+        })?;
+        let heap_file = OpenOptions::new().read(true).open(heap_file_path)?;
+        Ok(DiskReader {
             heap_file,
-            next_page_id,
+            max_io_attempts: self.max_io_attempts,
         })
     }
 
-    pub fn open(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
-        let heap_file = OpenOptions::new()
+    fn open_heap_file(heap_file_path: impl AsRef<Path>) -> io::Result<File> {
+        OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(heap_file_path)?;
-        Self::new(heap_file)
+            .truncate(false)
+            .open(heap_file_path)
+    }
+
+    /// Makes the filesystem set aside space for `pages` pages past the current
+    /// last page, so writes into them can't fail with ENOSPC. The page count
+    /// is unchanged. On Linux this uses fallocate(FALLOC_FL_KEEP_SIZE); other
+    /// platforms fall back to growing the file with set_len, so the reserved
+    /// pages show up as zeroed pages when the file is reopened.
+    pub fn reserve(&mut self, pages: u64) -> io::Result<()> {
+        self.check_writable()?;
+        let (heap_file, next_page_id) = self.locate(PageId(self.next_page_id));
+        let offset = PAGE_SIZE as u64 * next_page_id.to_u64();
+        let len = PAGE_SIZE as u64 * pages;
+        if len == 0 {
+            return Ok(());
+        }
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            let (Ok(offset), Ok(len)) = (i64::try_from(offset), i64::try_from(len)) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "reservation exceeds the maximum file size",
+                ));
+            };
+            // SAFETY: fallocate only reads its integer arguments and the fd
+            // is owned by `heap_file` for the duration of the call.
+            let ret = unsafe {
+                libc::fallocate(
+                    heap_file.as_raw_fd(),
+                    libc::FALLOC_FL_KEEP_SIZE,
+                    offset,
+                    len,
+                )
+            };
+            if ret == 0 {
+                return Ok(());
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Unsupported {
+                return Err(err);
+            }
+        }
+        let file_len = heap_file.metadata()?.len();
+        if file_len < offset + len {
+            heap_file.set_len(offset + len)?;
+        }
+        Ok(())
+    }
+
+    /// Moves pages `boundary..` out of the last file into a new file at
+    /// `new_path`, after which those pages are read and written there. Only
+    /// the current last file can be split. The split is not recorded on disk:
+    /// reopen the heap with `open_sharded`, since the original file alone
+    /// holds just the pages before the boundary. The new file is locked as by
+    /// `open`.
+    pub fn split_file(&mut self, boundary: PageId, new_path: impl AsRef<Path>) -> io::Result<()> {
+        self.check_writable()?;
+        let new_path = new_path.as_ref();
+        let last_first_page_id = self.shards.last().map_or(0, |shard| shard.first_page_id);
+        if boundary.to_u64() <= last_first_page_id || boundary.to_u64() > self.next_page_id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "can only split pages {}..={} off the last file, got {}",
+                    last_first_page_id + 1,
+                    self.next_page_id,
+                    boundary.to_u64()
+                ),
+            ));
+        }
+        let mut new_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(new_path)?;
+        let (old_file, local_boundary) = self.locate(boundary);
+        let split_offset = PAGE_SIZE as u64 * local_boundary.to_u64();
+        let copied = (|| {
+            lock_heap_file(&new_file, new_path, false)?;
+            old_file.seek(io::SeekFrom::Start(split_offset))?;
+            io::copy(old_file, &mut new_file)?;
+            new_file.sync_all()
+        })();
+        if let Err(err) = copied {
+            // nothing has been cut yet, so the partial copy is just garbage
+            drop(new_file);
+            let _ = remove_file(new_path);
+            return Err(err);
+        }
+        // the moved pages are durable in the new file before they are cut
+        old_file.set_len(split_offset)?;
+        old_file.sync_all()?;
+        self.shards.push(Shard {
+            first_page_id: boundary.to_u64(),
+            file: new_file,
+        });
+        Ok(())
+    }
+}
+
+impl<B: Backend> DiskManager<B> {
+    /// Serves pages from any seekable byte store, e.g. an in-memory cursor or
+    /// an encrypting wrapper around a file.
+    pub fn from_backend(mut heap_file: B) -> io::Result<Self> {
+        let heap_file_size = heap_file.seek(io::SeekFrom::End(0))?;
+        let next_page_id = heap_file_size / PAGE_SIZE as u64;
+        Ok(Self {
+            heap_file,
+            heap_file_path: None,
+            next_page_id,
+            read_only: false,
+            max_io_attempts: DEFAULT_MAX_IO_ATTEMPTS,
+            verify_writes: false,
+            shards: vec![],
+        })
+    }
+
+    /// How many times a page read or write is tried in total when it keeps
+    /// failing with a transient error (`Interrupted` or `WouldBlock`).
+    pub fn set_max_io_attempts(&mut self, max_io_attempts: u32) {
+        assert!(max_io_attempts > 0, "max_io_attempts must be positive");
+        self.max_io_attempts = max_io_attempts;
+    }
+
+    /// Reads every page back after writing it and fails with `InvalidData` if
+    /// the bytes differ. A diagnostic for storage that silently drops writes;
+    /// it doubles the I/O and, for files, mostly checks the page cache.
+    pub fn set_verify_writes(&mut self, verify_writes: bool) {
+        self.verify_writes = verify_writes;
     }
 
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "trace", skip_all, fields(page_id = page_id.0))
+    )]
     pub fn read_page_data(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
-        let offset = PAGE_SIZE as u64 * page_id.to_u64();
-        self.heap_file.seek(io::SeekFrom::Start(offset))?;
-        self.heap_file.read_exact(data)
+        check_page_len(data.len())?;
+        self.read_partial(page_id, data)
+    }
+
+    /// Reads only the first `data.len()` bytes of the page.
+    pub fn read_partial(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        if data.len() > PAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("partial read of {} bytes exceeds page size", data.len()),
+            ));
+        }
+        let max_io_attempts = self.max_io_attempts;
+        let (heap_file, page_id) = self.locate(page_id);
+        retry_transient(max_io_attempts, || read_page_at(heap_file, page_id, data))
+    }
+
+    /// Reads scattered pages with one read per run of adjacent page ids.
+    /// `bufs[i]` receives page `ids[i]`.
+    pub fn read_pages_gather(
+        &mut self,
+        ids: &[PageId],
+        bufs: &mut [[u8; PAGE_SIZE]],
+    ) -> io::Result<()> {
+        if ids.len() != bufs.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} page ids but {} buffers", ids.len(), bufs.len()),
+            ));
+        }
+        let max_io_attempts = self.max_io_attempts;
+        let mut run = vec![];
+        for gather_run in gather_runs(ids) {
+            let first_page_id = PageId(gather_run.first_page_id);
+            if self.shard_end(first_page_id) < gather_run.first_page_id + gather_run.page_count {
+                // the run crosses into the next file
+                for (page_in_run, buf_index) in gather_run.targets {
+                    let page_id = PageId(gather_run.first_page_id + page_in_run);
+                    self.read_page_data(page_id, &mut bufs[buf_index])?;
+                }
+                continue;
+            }
+            run.resize(gather_run.page_count as usize * PAGE_SIZE, 0);
+            let (heap_file, first_page_id) = self.locate(first_page_id);
+            retry_transient(max_io_attempts, || {
+                read_page_at(heap_file, first_page_id, &mut run)
+            })?;
+            for (page_in_run, buf_index) in gather_run.targets {
+                let start = page_in_run as usize * PAGE_SIZE;
+                bufs[buf_index].copy_from_slice(&run[start..start + PAGE_SIZE]);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_into_vec(&mut self, page_id: PageId) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; PAGE_SIZE];
+        self.read_page_data(page_id, &mut data)?;
+        Ok(data)
+    }
+
+    /// Sends pages `start..end` to `out`, each as its little-endian u64 page id
+    /// followed by the page bytes, and returns how many were sent.
+    pub fn stream_pages(
+        &mut self,
+        mut out: impl Write,
+        start: PageId,
+        end: PageId,
+    ) -> io::Result<u64> {
+        if start.to_u64() > end.to_u64() || end.to_u64() > self.next_page_id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "cannot stream pages {}..{} of {}",
+                    start.to_u64(),
+                    end.to_u64(),
+                    self.next_page_id
+                ),
+            ));
+        }
+        let mut data = vec![0; PAGE_SIZE];
+        for page_id in start.to_u64()..end.to_u64() {
+            self.read_page_data(PageId(page_id), &mut data)?;
+            out.write_all(&page_id.to_le_bytes())?;
+            out.write_all(&data)?;
+        }
+        out.flush()?;
+        Ok(end.to_u64() - start.to_u64())
+    }
+
+    /// Writes every page framed by `stream_pages` to its page id, allocating up
+    /// to the highest id received, and returns how many pages were read.
+    pub fn ingest_pages(&mut self, mut input: impl Read) -> io::Result<u64> {
+        self.check_writable()?;
+        let mut data = vec![0; PAGE_SIZE];
+        let mut count = 0;
+        while let Some(page_id) = read_frame_page_id(&mut input)? {
+            if page_id
+                .checked_add(1)
+                .and_then(|end| end.checked_mul(PAGE_SIZE as u64))
+                .is_none()
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("page id {page_id} in stream is out of range"),
+                ));
+            }
+            input.read_exact(&mut data)?;
+            self.write_page_data(PageId(page_id), &data)?;
+            self.next_page_id = self.next_page_id.max(page_id + 1);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// A cheap 64-bit FNV-1a hash of the page's current bytes, for telling
+    /// whether a page changed since a backup without shipping it. Not
+    /// collision-resistant against deliberate tampering.
+    pub fn page_digest(&mut self, page_id: PageId) -> io::Result<u64> {
+        let mut data = vec![0; PAGE_SIZE];
+        self.read_page_data(page_id, &mut data)?;
+        Ok(fnv1a(&data))
+    }
+
+    /// `digests[i]` is the digest of page `i`.
+    pub fn digest_all(&mut self) -> io::Result<Vec<u64>> {
+        let mut data = vec![0; PAGE_SIZE];
+        (0..self.next_page_id)
+            .map(|page_id| {
+                self.read_page_data(PageId(page_id), &mut data)?;
+                Ok(fnv1a(&data))
+            })
+            .collect()
+    }
+
+    /// Reads every allocated page in order and hands it to `f`. The page is
+    /// written back only when `f` returns true. Stops at the first error.
+    pub fn for_each_page_mut(
+        &mut self,
+        mut f: impl FnMut(PageId, &mut [u8]) -> io::Result<bool>,
+    ) -> io::Result<()> {
+        let mut data = vec![0; PAGE_SIZE];
+        for page_id in (0..self.next_page_id).map(PageId) {
+            self.read_page_data(page_id, &mut data)?;
+            if f(page_id, &mut data)? {
+                self.write_page_data(page_id, &data)?;
+            }
+        }
+        Ok(())
     }
 
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "trace", skip_all, fields(page_id = page_id.0))
+    )]
     pub fn write_page_data(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
-        let offset = PAGE_SIZE as u64 * page_id.to_u64();
-        self.heap_file.seek(io::SeekFrom::Start(offset))?;
-        self.heap_file.write_all(data)
+        self.check_writable()?;
+        check_page_len(data.len())?;
+        let max_io_attempts = self.max_io_attempts;
+        let verify_writes = self.verify_writes;
+        let (heap_file, local_page_id) = self.locate(page_id);
+        let offset = PAGE_SIZE as u64 * local_page_id.to_u64();
+        retry_transient(max_io_attempts, || {
+            heap_file.seek(io::SeekFrom::Start(offset))?;
+            heap_file.write_all(data)
+        })?;
+        if verify_writes {
+            let mut written = vec![0; PAGE_SIZE];
+            retry_transient(max_io_attempts, || {
+                read_page_at(heap_file, local_page_id, &mut written)
+            })?;
+            if written != data {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("write verification failed for page {}", page_id.to_u64()),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Overwrites only `bytes.len()` bytes starting `offset_in_page` bytes into
+    /// the page, e.g. to patch a header field.
+    pub fn write_at(
+        &mut self,
+        page_id: PageId,
+        offset_in_page: usize,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        self.check_writable()?;
+        match offset_in_page.checked_add(bytes.len()) {
+            Some(end) if end <= PAGE_SIZE => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "write of {} bytes at offset {} exceeds page size",
+                        bytes.len(),
+                        offset_in_page
+                    ),
+                ))
+            }
+        }
+        let max_io_attempts = self.max_io_attempts;
+        let (heap_file, page_id) = self.locate(page_id);
+        let offset = PAGE_SIZE as u64 * page_id.to_u64() + offset_in_page as u64;
+        retry_transient(max_io_attempts, || {
+            heap_file.seek(io::SeekFrom::Start(offset))?;
+            heap_file.write_all(bytes)
+        })
+    }
+
+    /// Writes `new` over the region only if it currently holds `expected`, and
+    /// returns whether it did. This is atomic only because the manager is
+    /// used from one thread at a time; concurrent callers need the page latch.
+    pub fn cas_page_region(
+        &mut self,
+        page_id: PageId,
+        offset_in_page: usize,
+        expected: &[u8],
+        new: &[u8],
+    ) -> io::Result<bool> {
+        self.check_writable()?;
+        if expected.len() != new.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "expected {} bytes but got {} replacement bytes",
+                    expected.len(),
+                    new.len()
+                ),
+            ));
+        }
+        let end = offset_in_page
+            .checked_add(expected.len())
+            .filter(|&end| end <= PAGE_SIZE)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "region of {} bytes at offset {} exceeds page size",
+                        expected.len(),
+                        offset_in_page
+                    ),
+                )
+            })?;
+        let mut current = vec![0; end];
+        self.read_partial(page_id, &mut current)?;
+        if &current[offset_in_page..] != expected {
+            return Ok(false);
+        }
+        self.write_at(page_id, offset_in_page, new)?;
+        Ok(true)
     }
 
-    pub fn allocate_page(&mut self) -> PageId {
+    pub fn allocate_page(&mut self) -> io::Result<PageId> {
+        self.check_writable()?;
         let page_id = self.next_page_id;
         self.next_page_id += 1;
-        PageId(page_id)
+        Ok(PageId(page_id))
+    }
+
+    /// Returns the first of `count` consecutive, freshly allocated page ids.
+    pub fn allocate_contiguous(&mut self, count: usize) -> io::Result<PageId> {
+        self.check_writable()?;
+        if count == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot allocate an empty run of pages",
+            ));
+        }
+        let page_id = self.next_page_id;
+        self.next_page_id += count as u64;
+        Ok(PageId(page_id))
+    }
+
+    pub fn append_page(&mut self, data: &[u8]) -> io::Result<PageId> {
+        check_page_len(data.len())?;
+        let page_id = self.allocate_page()?;
+        if let Err(err) = self.write_page_data(page_id, data) {
+            self.next_page_id -= 1;
+            return Err(err);
+        }
+        Ok(page_id)
     }
 
+    #[cfg_attr(feature = "trace", tracing::instrument(level = "trace", skip_all))]
     pub fn sync(&mut self) -> io::Result<()> {
-        self.heap_file.flush()?;
-        self.heap_file.sync_all()
+        self.check_writable()?;
+        self.heap_file.sync()?;
+        for shard in &mut self.shards {
+            shard.file.sync()?;
+        }
+        Ok(())
+    }
+
+    pub fn page_count(&self) -> u64 {
+        self.next_page_id
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    // Returns the file holding `page_id` and the page's id within that file.
+    fn locate(&mut self, page_id: PageId) -> (&mut B, PageId) {
+        match self
+            .shards
+            .iter_mut()
+            .rev()
+            .find(|shard| shard.first_page_id <= page_id.to_u64())
+        {
+            Some(shard) => (
+                &mut shard.file,
+                PageId(page_id.to_u64() - shard.first_page_id),
+            ),
+            None => (&mut self.heap_file, page_id),
+        }
+    }
+
+    // The first page id past the file holding `page_id`.
+    fn shard_end(&self, page_id: PageId) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.first_page_id)
+            .find(|&first_page_id| first_page_id > page_id.to_u64())
+            .unwrap_or(u64::MAX)
+    }
+
+    fn check_writable(&self) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::ReadOnlyFilesystem,
+                "disk manager is opened read-only",
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Summarizes the manager for logging rather than dumping the backend, which
+// for an in-memory heap would be every page.
+impl<B: Backend> fmt::Debug for DiskManager<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiskManager")
+            .field("heap_file_path", &self.heap_file_path)
+            .field("page_count", &self.page_count())
+            .field("page_size", &PAGE_SIZE)
+            .field("read_only", &self.read_only)
+            .field("verify_writes", &self.verify_writes)
+            .field("shards", &self.shards.len())
+            .finish()
     }
 }
 
+impl<B: Backend> fmt::Display for DiskManager<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.heap_file_path {
+            Some(heap_file_path) => write!(f, "{}: ", heap_file_path.display())?,
+            None => write!(f, "<unnamed>: ")?,
+        }
+        write!(f, "{} pages of {} bytes", self.page_count(), PAGE_SIZE)?;
+        if self.read_only {
+            write!(f, " (read-only)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct GatherRun {
+    first_page_id: u64,
+    page_count: u64,
+    // (page offset within the run, index into the caller's buffers)
+    targets: Vec<(u64, usize)>,
+}
+
+fn gather_runs(ids: &[PageId]) -> Vec<GatherRun> {
+    let mut order: Vec<usize> = (0..ids.len()).collect();
+    order.sort_by_key(|&i| ids[i].to_u64());
+    let mut runs: Vec<GatherRun> = vec![];
+    for i in order {
+        let page_id = ids[i].to_u64();
+        match runs.last_mut() {
+            Some(run) if page_id <= run.first_page_id + run.page_count => {
+                run.page_count = run.page_count.max(page_id - run.first_page_id + 1);
+                run.targets.push((page_id - run.first_page_id, i));
+            }
+            _ => runs.push(GatherRun {
+                first_page_id: page_id,
+                page_count: 1,
+                targets: vec![(0, i)],
+            }),
+        }
+    }
+    runs
+}
+
+#[derive(Debug)]
+pub struct DiskReader {
+    heap_file: File,
+    max_io_attempts: u32,
+}
+
+impl DiskReader {
+    pub fn read_page_data(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        check_page_len(data.len())?;
+        let heap_file = &mut self.heap_file;
+        retry_transient(self.max_io_attempts, || {
+            read_page_at(heap_file, page_id, data)
+        })
+    }
+}
+
+fn read_page_at(
+    heap_file: &mut (impl Read + Seek),
+    page_id: PageId,
+    data: &mut [u8],
+) -> io::Result<()> {
+    let offset = PAGE_SIZE as u64 * page_id.to_u64();
+    heap_file.seek(io::SeekFrom::Start(offset))?;
+    heap_file.read_exact(data)
+}
+
+// Seeks, reads and writes at a fixed offset are idempotent, so an attempt
+// that failed transiently can simply be repeated. `WouldBlock` backs off a little
+// longer on each attempt.
+fn retry_transient<T>(max_attempts: u32, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(err) if attempt < max_attempts => match err.kind() {
+                io::ErrorKind::Interrupted => {}
+                io::ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(attempt as u64)),
+                _ => return Err(err),
+            },
+            result => return result,
+        }
+        attempt += 1;
+    }
+}
+
+pub(crate) fn lock_heap_file(
+    heap_file: &File,
+    heap_file_path: &Path,
+    shared: bool,
+) -> io::Result<()> {
+    let locked = if shared {
+        heap_file.try_lock_shared()
+    } else {
+        heap_file.try_lock()
+    };
+    match locked {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(io::Error::new(
+            io::ErrorKind::ResourceBusy,
+            format!("{} is already open", heap_file_path.display()),
+        )),
+        Err(TryLockError::Error(err)) => Err(err),
+    }
+}
+
+// Returns `None` on a clean end of stream between frames.
+fn read_frame_page_id(input: &mut impl Read) -> io::Result<Option<u64>> {
+    let mut page_id = [0; 8];
+    let mut filled = 0;
+    while filled < page_id.len() {
+        match input.read(&mut page_id[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "page stream ended inside a frame",
+                ))
+            }
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(Some(u64::from_le_bytes(page_id)))
+}
+
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn check_page_len(len: usize) -> io::Result<()> {
+    if len != PAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected {} bytes of page data, got {}", PAGE_SIZE, len),
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test_page_id {
     use super::PageId;
@@ -122,6 +909,7 @@ mod test_page_id {
         use crate::disk::PageId;
 
         #[test]
+        #[allow(clippy::useless_conversion)]
         fn test_from_some() {
             assert_eq!(PageId::from(PageId(0)), PageId(0));
         }
@@ -144,11 +932,14 @@ mod test_disk_manager {
     use super::DiskManager;
 
     use std::{
-        fs::{remove_file, File, OpenOptions},
-        io::{Read, Seek, Write},
+        fs::{create_dir, read_dir, remove_dir, remove_file, File, OpenOptions},
+        io::{self, Cursor, ErrorKind, Read, Seek, Write},
+        path::Path,
     };
 
-    use crate::disk::PageId;
+    use crate::disk::{
+        fnv1a, gather_runs, retry_transient, Backend, GatherRun, PageId, TornTail, PAGE_SIZE,
+    };
 
     #[test]
     fn test_new() {
@@ -195,69 +986,1276 @@ mod test_disk_manager {
         remove_file(file_name).unwrap();
     }
 
+    #[test]
+    fn test_open_checked_torn_tail() {
+        let file_name = "test_disk_manager_open_checked_torn_tail.txt";
+        create_tmp_file(file_name, &vec![1; PAGE_SIZE * 2 + 10]);
+
+        let (disk_manager, torn_tail) = DiskManager::open_checked(file_name).unwrap();
+
+        assert_eq!(
+            torn_tail,
+            Some(TornTail {
+                page_count: 2,
+                tail_len: 10
+            })
+        );
+        assert_eq!(disk_manager.next_page_id, 2);
+        assert_eq!(
+            disk_manager.heap_file.metadata().unwrap().len(),
+            PAGE_SIZE as u64 * 2
+        );
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_open_checked_aligned() {
+        let file_name = "test_disk_manager_open_checked_aligned.txt";
+        create_tmp_file(file_name, &vec![1; PAGE_SIZE * 2]);
+
+        let (disk_manager, torn_tail) = DiskManager::open_checked(file_name).unwrap();
+
+        assert_eq!(torn_tail, None);
+        assert_eq!(disk_manager.next_page_id, 2);
+
+        remove_file(file_name).unwrap();
+    }
+
     #[test]
     fn test_read_page_data() {
         let file_name = "test_disk_manager_read_page_data.txt";
+        let mut contents = vec![0; PAGE_SIZE * 2];
+        contents[PAGE_SIZE..PAGE_SIZE + 13].copy_from_slice(b"Hello, World!");
+        create_tmp_file(file_name, &contents);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let page_id = PageId(1);
+        let mut buf = vec![0; PAGE_SIZE];
+
+        disk_manager.read_page_data(page_id, &mut buf).unwrap();
+
+        assert_eq!(&buf[..13], b"Hello, World!");
+        assert!(buf[13..].iter().all(|&b| b == 0));
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_read_page_data_invalid_len() {
+        let file_name = "test_disk_manager_read_page_data_invalid_len.txt";
         create_tmp_file(file_name, b"Hello, World!");
 
         let mut disk_manager = DiskManager::open(file_name).unwrap();
-        let page_id = PageId(0);
         // len("Hello, World!") = 13
         let mut buf = vec![0; 13];
 
-        disk_manager.read_page_data(page_id, &mut buf).unwrap();
+        let err = disk_manager
+            .read_page_data(PageId(0), &mut buf)
+            .unwrap_err();
 
-        assert_eq!(buf, b"Hello, World!");
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(buf, vec![0; 13]);
 
         remove_file(file_name).unwrap();
     }
 
     #[test]
-    fn test_write_page_data() {
-        let file_name = "test_disk_manager_write_page_data.txt";
+    fn test_read_into_vec() {
+        let file_name = "test_disk_manager_read_into_vec.txt";
+        let contents: Vec<u8> = (0..2).flat_map(|i| [i as u8 + 1; PAGE_SIZE]).collect();
+        create_tmp_file(file_name, &contents);
 
         let mut disk_manager = DiskManager::open(file_name).unwrap();
-        let page_id = PageId(0);
-        let buf = b"Hello, World!";
 
-        disk_manager.write_page_data(page_id, buf).unwrap();
+        let buf = disk_manager.read_into_vec(PageId(1)).unwrap();
 
-        let mut contents = String::new();
+        assert_eq!(buf.len(), PAGE_SIZE);
+        assert_eq!(buf, vec![2; PAGE_SIZE]);
+        let err = disk_manager.read_into_vec(PageId(2)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_cas_page_region() {
+        let file_name = "test_disk_manager_cas_page_region.txt";
+        create_tmp_file(file_name, &[0; PAGE_SIZE * 2]);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
         disk_manager
-            .heap_file
-            .seek(std::io::SeekFrom::Start(0))
+            .write_at(PageId(1), 8, &7u64.to_le_bytes())
             .unwrap();
-        disk_manager
-            .heap_file
-            .read_to_string(&mut contents)
+
+        let swapped = disk_manager
+            .cas_page_region(PageId(1), 8, &7u64.to_le_bytes(), &9u64.to_le_bytes())
+            .unwrap();
+        assert!(swapped);
+        let swapped = disk_manager
+            .cas_page_region(PageId(1), 8, &7u64.to_le_bytes(), &11u64.to_le_bytes())
             .unwrap();
+        assert!(!swapped);
 
-        assert_eq!(contents, "Hello, World!");
+        let page = disk_manager.read_into_vec(PageId(1)).unwrap();
+        assert_eq!(&page[8..16], &9u64.to_le_bytes());
+        assert!(page[..8].iter().chain(&page[16..]).all(|&b| b == 0));
 
         remove_file(file_name).unwrap();
     }
 
     #[test]
-    fn test_allocate_page() {
-        let file_name = "test_disk_manager_write_page_data.txt";
+    fn test_cas_page_region_invalid() {
+        let file_name = "test_disk_manager_cas_page_region_invalid.txt";
+        create_tmp_file(file_name, &[0; PAGE_SIZE]);
 
         let mut disk_manager = DiskManager::open(file_name).unwrap();
 
-        assert_eq!(disk_manager.next_page_id, 0);
-        disk_manager.allocate_page();
-        assert_eq!(disk_manager.next_page_id, 1);
+        let err = disk_manager
+            .cas_page_region(PageId(0), 0, &[0; 2], &[1; 3])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let err = disk_manager
+            .cas_page_region(PageId(0), PAGE_SIZE - 1, &[0; 2], &[1; 2])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(
+            disk_manager.read_into_vec(PageId(0)).unwrap(),
+            [0; PAGE_SIZE]
+        );
 
         remove_file(file_name).unwrap();
     }
 
-    fn create_tmp_file(file_name: &str, contents: &[u8]) -> File {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .read(true)
-            .create(true)
-            .open(file_name)
+    #[test]
+    fn test_stream_pages() {
+        let file_name = "test_disk_manager_stream_pages.txt";
+        let contents: Vec<u8> = (0..6).flat_map(|i| [i as u8; PAGE_SIZE]).collect();
+        create_tmp_file(file_name, &contents);
+
+        let mut source = DiskManager::open(file_name).unwrap();
+        let mut pipe = vec![];
+        let sent = source
+            .stream_pages(&mut pipe, PageId(2), PageId(5))
             .unwrap();
-        file.write_all(contents).unwrap();
-        return file;
+
+        assert_eq!(sent, 3);
+        assert_eq!(pipe.len(), (8 + PAGE_SIZE) * 3);
+        let mut copy = DiskManager::from_backend(Cursor::new(vec![])).unwrap();
+        let received = copy.ingest_pages(&pipe[..]).unwrap();
+        assert_eq!(received, 3);
+        assert_eq!(copy.page_count(), 5);
+        for i in 2..5 {
+            assert_eq!(
+                copy.read_into_vec(PageId(i)).unwrap(),
+                source.read_into_vec(PageId(i)).unwrap()
+            );
+        }
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_stream_pages_invalid() {
+        let mut disk_manager = DiskManager::from_backend(Cursor::new(vec![0; PAGE_SIZE])).unwrap();
+
+        let err = disk_manager
+            .stream_pages(io::sink(), PageId(0), PageId(2))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let mut pipe = vec![];
+        disk_manager
+            .stream_pages(&mut pipe, PageId(0), PageId(1))
+            .unwrap();
+        let err = disk_manager
+            .ingest_pages(&pipe[..pipe.len() - 1])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        let err = disk_manager.ingest_pages(&pipe[..3]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        pipe[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let err = disk_manager.ingest_pages(&pipe[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        pipe[..8].copy_from_slice(&(u64::MAX / PAGE_SIZE as u64).to_le_bytes());
+        let err = disk_manager.ingest_pages(&pipe[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(disk_manager.page_count(), 1);
+    }
+
+    #[test]
+    fn test_page_digest() {
+        let file_name = "test_disk_manager_page_digest.txt";
+        let contents: Vec<u8> = (0..3).flat_map(|i| [i as u8; PAGE_SIZE]).collect();
+        create_tmp_file(file_name, &contents);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let before = disk_manager.digest_all().unwrap();
+
+        disk_manager.write_at(PageId(1), 100, &[0xff]).unwrap();
+
+        let after = disk_manager.digest_all().unwrap();
+        assert_eq!(before.len(), 3);
+        assert_eq!(after[0], before[0]);
+        assert_ne!(after[1], before[1]);
+        assert_eq!(after[2], before[2]);
+        assert_eq!(disk_manager.page_digest(PageId(1)).unwrap(), after[1]);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_for_each_page_mut() {
+        let file_name = "test_disk_manager_for_each_page_mut.txt";
+        let contents: Vec<u8> = (0..4).flat_map(|i| [i as u8; PAGE_SIZE]).collect();
+        create_tmp_file(file_name, &contents);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let mut visited = vec![];
+
+        disk_manager
+            .for_each_page_mut(|page_id, data| {
+                visited.push(page_id);
+                data[0] += 1;
+                // leave the last page unwritten
+                Ok(page_id != PageId(3))
+            })
+            .unwrap();
+
+        assert_eq!(visited, vec![PageId(0), PageId(1), PageId(2), PageId(3)]);
+        for i in 0..4 {
+            let page = disk_manager.read_into_vec(PageId(i)).unwrap();
+            let expected = if i == 3 { i as u8 } else { i as u8 + 1 };
+            assert_eq!(page[0], expected);
+            assert!(page[1..].iter().all(|&b| b == i as u8));
+        }
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_for_each_page_mut_error() {
+        let file_name = "test_disk_manager_for_each_page_mut_error.txt";
+        create_tmp_file(file_name, &[0; PAGE_SIZE * 3]);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let mut visited = 0;
+
+        let err = disk_manager
+            .for_each_page_mut(|page_id, _| {
+                visited += 1;
+                if page_id == PageId(1) {
+                    return Err(io::Error::other("stop"));
+                }
+                Ok(false)
+            })
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "stop");
+        assert_eq!(visited, 2);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_read_pages_gather() {
+        let contents: Vec<u8> = (0..13).flat_map(|i| [i as u8; PAGE_SIZE]).collect();
+        let mut disk_manager = DiskManager::from_backend(CountingBackend {
+            inner: Cursor::new(contents),
+            reads: 0,
+        })
+        .unwrap();
+        let ids = [PageId(12), PageId(6), PageId(5), PageId(7), PageId(6)];
+        let mut bufs = vec![[0u8; PAGE_SIZE]; ids.len()];
+
+        disk_manager.read_pages_gather(&ids, &mut bufs).unwrap();
+
+        for (page_id, buf) in ids.iter().zip(&bufs) {
+            assert_eq!(*buf, [page_id.to_u64() as u8; PAGE_SIZE]);
+        }
+        // one read for 5..=7 and one for 12
+        assert_eq!(disk_manager.heap_file.reads, 2);
+    }
+
+    #[test]
+    fn test_read_pages_gather_len_mismatch() {
+        let file_name = "test_disk_manager_read_pages_gather_len_mismatch.txt";
+        create_tmp_file(file_name, &[0; PAGE_SIZE]);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let mut bufs = vec![[0u8; PAGE_SIZE]; 1];
+
+        let err = disk_manager
+            .read_pages_gather(&[PageId(0), PageId(0)], &mut bufs)
+            .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_retry_transient() {
+        for kind in [ErrorKind::Interrupted, ErrorKind::WouldBlock] {
+            let mut calls = 0;
+
+            let result = retry_transient(3, || {
+                calls += 1;
+                if calls <= 2 {
+                    Err(std::io::Error::from(kind))
+                } else {
+                    Ok(calls)
+                }
+            });
+
+            assert_eq!(result.unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn test_retry_transient_gives_up() {
+        let mut calls = 0;
+
+        let err = retry_transient(2, || {
+            calls += 1;
+            Err::<(), _>(std::io::Error::from(ErrorKind::Interrupted))
+        })
+        .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_retry_transient_other_error() {
+        let mut calls = 0;
+
+        let err = retry_transient(3, || {
+            calls += 1;
+            Err::<(), _>(std::io::Error::from(ErrorKind::PermissionDenied))
+        })
+        .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_gather_runs() {
+        let ids = [PageId(5), PageId(6), PageId(7), PageId(12)];
+
+        assert_eq!(
+            gather_runs(&ids),
+            vec![
+                GatherRun {
+                    first_page_id: 5,
+                    page_count: 3,
+                    targets: vec![(0, 0), (1, 1), (2, 2)],
+                },
+                GatherRun {
+                    first_page_id: 12,
+                    page_count: 1,
+                    targets: vec![(0, 3)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gather_runs_duplicates() {
+        let ids = [PageId(3), PageId(2), PageId(3)];
+
+        assert_eq!(
+            gather_runs(&ids),
+            vec![GatherRun {
+                first_page_id: 2,
+                page_count: 2,
+                targets: vec![(0, 1), (1, 0), (1, 2)],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_read_partial() {
+        let file_name = "test_disk_manager_read_partial.txt";
+        create_tmp_file(file_name, b"Hello, World!");
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let page_id = PageId(0);
+        // len("Hello, World!") = 13
+        let mut buf = vec![0; 13];
+
+        disk_manager.read_partial(page_id, &mut buf).unwrap();
+
+        assert_eq!(buf, b"Hello, World!");
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_read_partial_too_long() {
+        let file_name = "test_disk_manager_read_partial_too_long.txt";
+        create_tmp_file(file_name, &vec![0; PAGE_SIZE * 2]);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let mut buf = vec![0; PAGE_SIZE + 1];
+
+        let err = disk_manager.read_partial(PageId(0), &mut buf).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_write_page_data() {
+        let file_name = "test_disk_manager_write_page_data.txt";
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let page_id = PageId(0);
+        let mut buf = vec![0; PAGE_SIZE];
+        buf[..13].copy_from_slice(b"Hello, World!");
+
+        disk_manager.write_page_data(page_id, &buf).unwrap();
+
+        let mut contents = Vec::new();
+        disk_manager
+            .heap_file
+            .seek(std::io::SeekFrom::Start(0))
+            .unwrap();
+        disk_manager.heap_file.read_to_end(&mut contents).unwrap();
+
+        assert_eq!(contents, buf);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_write_page_data_invalid_len() {
+        let file_name = "test_disk_manager_write_page_data_invalid_len.txt";
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+
+        let err = disk_manager
+            .write_page_data(PageId(0), b"Hello, World!")
+            .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(disk_manager.heap_file.metadata().unwrap().len(), 0);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_allocate_page() {
+        let file_name = "test_disk_manager_allocate_page.txt";
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+
+        assert_eq!(disk_manager.next_page_id, 0);
+        assert_eq!(disk_manager.allocate_page().unwrap(), PageId(0));
+        assert_eq!(disk_manager.next_page_id, 1);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_write_at() {
+        let file_name = "test_disk_manager_write_at.txt";
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        disk_manager.append_page(&[1; PAGE_SIZE]).unwrap();
+        disk_manager.append_page(&[1; PAGE_SIZE]).unwrap();
+
+        disk_manager.write_at(PageId(1), 16, &[2; 8]).unwrap();
+
+        let mut expected = vec![1; PAGE_SIZE];
+        expected[16..24].copy_from_slice(&[2; 8]);
+        assert_eq!(disk_manager.read_into_vec(PageId(1)).unwrap(), expected);
+        assert_eq!(
+            disk_manager.read_into_vec(PageId(0)).unwrap(),
+            vec![1; PAGE_SIZE]
+        );
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_write_at_out_of_bounds() {
+        let file_name = "test_disk_manager_write_at_out_of_bounds.txt";
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        disk_manager.append_page(&[1; PAGE_SIZE]).unwrap();
+
+        let err = disk_manager
+            .write_at(PageId(0), PAGE_SIZE - 4, &[2; 8])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let err = disk_manager
+            .write_at(PageId(0), usize::MAX, &[2; 8])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        assert_eq!(
+            disk_manager.read_into_vec(PageId(0)).unwrap(),
+            vec![1; PAGE_SIZE]
+        );
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_page_count() {
+        let file_name = "test_disk_manager_page_count.txt";
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        for _ in 0..3 {
+            disk_manager.allocate_page().unwrap();
+        }
+
+        assert_eq!(disk_manager.page_count(), 3);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_display() {
+        let file_name = "test_disk_manager_display.txt";
+        let file = create_tmp_file(file_name, &[0; PAGE_SIZE * 2]);
+
+        assert_eq!(
+            DiskManager::open(file_name).unwrap().to_string(),
+            "test_disk_manager_display.txt: 2 pages of 4096 bytes"
+        );
+        assert_eq!(
+            DiskManager::open_read_only(file_name).unwrap().to_string(),
+            "test_disk_manager_display.txt: 2 pages of 4096 bytes (read-only)"
+        );
+        assert_eq!(
+            DiskManager::new(file).unwrap().to_string(),
+            "<unnamed>: 2 pages of 4096 bytes"
+        );
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_debug() {
+        let disk_manager = DiskManager::from_backend(Cursor::new(vec![7; PAGE_SIZE * 2])).unwrap();
+
+        assert_eq!(
+            format!("{disk_manager:?}"),
+            "DiskManager { heap_file_path: None, page_count: 2, page_size: 4096, \
+             read_only: false, verify_writes: false, shards: 0 }"
+        );
+    }
+
+    #[test]
+    fn test_allocate_contiguous() {
+        let file_name = "test_disk_manager_allocate_contiguous.txt";
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        disk_manager.allocate_page().unwrap();
+
+        let first = disk_manager.allocate_contiguous(10).unwrap();
+
+        assert_eq!(first, PageId(1));
+        assert_eq!(disk_manager.page_count(), 11);
+        assert_eq!(disk_manager.allocate_page().unwrap(), PageId(11));
+        for i in 0..10 {
+            let page_id = PageId(first.to_u64() + i);
+            disk_manager
+                .write_page_data(page_id, &[i as u8; PAGE_SIZE])
+                .unwrap();
+        }
+        assert_eq!(
+            disk_manager.read_into_vec(PageId(5)).unwrap(),
+            vec![4; PAGE_SIZE]
+        );
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_allocate_contiguous_empty() {
+        let file_name = "test_disk_manager_allocate_contiguous_empty.txt";
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+
+        let err = disk_manager.allocate_contiguous(0).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(disk_manager.page_count(), 0);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_append_page() {
+        let file_name = "test_disk_manager_append_page.txt";
+        create_tmp_file(file_name, &[0; PAGE_SIZE]);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let data: Vec<u8> = (0..PAGE_SIZE).map(|i| i as u8).collect();
+
+        let page_id = disk_manager.append_page(&data).unwrap();
+
+        assert_eq!(page_id, PageId(1));
+        assert_eq!(disk_manager.read_into_vec(page_id).unwrap(), data);
+        assert_eq!(disk_manager.next_page_id, 2);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_append_page_invalid_len() {
+        let file_name = "test_disk_manager_append_page_invalid_len.txt";
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+
+        let err = disk_manager.append_page(b"Hello, World!").unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(disk_manager.next_page_id, 0);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_open_temp() {
+        let mut disk_manager = DiskManager::open_temp().unwrap();
+
+        let page_id = disk_manager.append_page(&[1; PAGE_SIZE]).unwrap();
+
+        assert_eq!(
+            disk_manager.read_into_vec(page_id).unwrap(),
+            vec![1; PAGE_SIZE]
+        );
+    }
+
+    #[test]
+    fn test_open_temp_in() {
+        let dir = "test_disk_manager_open_temp_in";
+        create_dir(dir).unwrap();
+
+        let mut disk_manager = DiskManager::open_temp_in(dir).unwrap();
+        for i in 0..3 {
+            disk_manager.append_page(&[i; PAGE_SIZE]).unwrap();
+        }
+
+        assert_eq!(
+            disk_manager.read_into_vec(PageId(1)).unwrap(),
+            vec![1; PAGE_SIZE]
+        );
+        assert_eq!(read_dir(dir).unwrap().count(), 0);
+
+        drop(disk_manager);
+
+        assert_eq!(read_dir(dir).unwrap().count(), 0);
+        remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_reader() {
+        let file_name = "test_disk_manager_open_reader.txt";
+        let contents: Vec<u8> = (0..3).flat_map(|i| [i as u8; PAGE_SIZE]).collect();
+        create_tmp_file(file_name, &contents);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let mut reader = disk_manager.open_reader().unwrap();
+        let mut buf = vec![0; PAGE_SIZE];
+
+        disk_manager.read_page_data(PageId(2), &mut buf).unwrap();
+        assert_eq!(buf, vec![2; PAGE_SIZE]);
+        reader.read_page_data(PageId(0), &mut buf).unwrap();
+        assert_eq!(buf, vec![0; PAGE_SIZE]);
+        disk_manager.read_page_data(PageId(1), &mut buf).unwrap();
+        assert_eq!(buf, vec![1; PAGE_SIZE]);
+        reader.read_page_data(PageId(2), &mut buf).unwrap();
+        assert_eq!(buf, vec![2; PAGE_SIZE]);
+
+        disk_manager
+            .write_page_data(PageId(1), &[9; PAGE_SIZE])
+            .unwrap();
+        reader.read_page_data(PageId(1), &mut buf).unwrap();
+        assert_eq!(buf, vec![9; PAGE_SIZE]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_open_reader_without_path() {
+        let file_name = "test_disk_manager_open_reader_without_path.txt";
+        let file = create_tmp_file(file_name, &[0; PAGE_SIZE]);
+
+        let disk_manager = DiskManager::new(file).unwrap();
+
+        let err = disk_manager.open_reader().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_reserve() {
+        use std::os::unix::fs::MetadataExt;
+
+        let file_name = "test_disk_manager_reserve.txt";
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        disk_manager.append_page(&[1; PAGE_SIZE]).unwrap();
+        let allocated_before = disk_manager.heap_file.metadata().unwrap().blocks() * 512;
+
+        disk_manager.reserve(16).unwrap();
+
+        let metadata = disk_manager.heap_file.metadata().unwrap();
+        assert!(metadata.blocks() * 512 >= allocated_before + PAGE_SIZE as u64 * 16);
+        assert_eq!(metadata.len(), PAGE_SIZE as u64);
+        assert_eq!(disk_manager.page_count(), 1);
+        drop(disk_manager);
+
+        assert_eq!(DiskManager::open(file_name).unwrap().page_count(), 1);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_reserve_read_only() {
+        let file_name = "test_disk_manager_reserve_read_only.txt";
+        create_tmp_file(file_name, &[1; PAGE_SIZE]);
+
+        let mut disk_manager = DiskManager::open_read_only(file_name).unwrap();
+
+        let err = disk_manager.reserve(1).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ReadOnlyFilesystem);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_split_file() {
+        let file_name = "test_disk_manager_split_file.txt";
+        let shard_name = "test_disk_manager_split_file_shard.txt";
+        let contents: Vec<u8> = (0..10).flat_map(|i| [i as u8; PAGE_SIZE]).collect();
+        create_tmp_file(file_name, &contents);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        disk_manager.split_file(PageId(5), shard_name).unwrap();
+
+        assert_eq!(disk_manager.page_count(), 10);
+        for i in 0..10 {
+            assert_eq!(
+                disk_manager.read_into_vec(PageId(i)).unwrap(),
+                [i as u8; PAGE_SIZE]
+            );
+        }
+        let ids = [PageId(6), PageId(4), PageId(5), PageId(3)];
+        let mut bufs = vec![[0u8; PAGE_SIZE]; ids.len()];
+        disk_manager.read_pages_gather(&ids, &mut bufs).unwrap();
+        for (page_id, buf) in ids.iter().zip(&bufs) {
+            assert_eq!(buf, &[page_id.to_u64() as u8; PAGE_SIZE]);
+        }
+        disk_manager
+            .write_page_data(PageId(7), &[70; PAGE_SIZE])
+            .unwrap();
+        let page_id = disk_manager.append_page(&[10; PAGE_SIZE]).unwrap();
+        disk_manager.sync().unwrap();
+
+        assert_eq!(page_id, PageId(10));
+        assert_eq!(
+            disk_manager.read_into_vec(PageId(7)).unwrap(),
+            [70; PAGE_SIZE]
+        );
+        assert_eq!(
+            std::fs::metadata(file_name).unwrap().len(),
+            PAGE_SIZE as u64 * 5
+        );
+        let mut shard = vec![];
+        File::open(shard_name)
+            .unwrap()
+            .read_to_end(&mut shard)
+            .unwrap();
+        assert_eq!(shard.len(), PAGE_SIZE * 6);
+        assert_eq!(shard[PAGE_SIZE * 2], 70);
+        assert_eq!(shard[PAGE_SIZE * 5], 10);
+        assert_eq!(
+            disk_manager.open_reader().unwrap_err().kind(),
+            ErrorKind::Unsupported
+        );
+
+        remove_file(file_name).unwrap();
+        remove_file(shard_name).unwrap();
+    }
+
+    #[test]
+    fn test_split_file_copy_fails() {
+        let file_name = "test_disk_manager_split_file_copy_fails.txt";
+        let shard_name = "test_disk_manager_split_file_copy_fails_shard.txt";
+        create_tmp_file(file_name, &[1; PAGE_SIZE * 4]);
+        // a write-only handle makes the copy's reads fail
+        let heap_file = OpenOptions::new().write(true).open(file_name).unwrap();
+        let mut disk_manager = DiskManager::new(heap_file).unwrap();
+
+        assert!(disk_manager.split_file(PageId(2), shard_name).is_err());
+
+        assert!(!Path::new(shard_name).exists());
+        assert_eq!(
+            std::fs::metadata(file_name).unwrap().len(),
+            PAGE_SIZE as u64 * 4
+        );
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_open_sharded() {
+        let file_name = "test_disk_manager_open_sharded.txt";
+        let shard_name = "test_disk_manager_open_sharded_shard.txt";
+        let partial_name = "test_disk_manager_open_sharded_partial.txt";
+        let contents: Vec<u8> = (0..10).flat_map(|i| [i as u8; PAGE_SIZE]).collect();
+        create_tmp_file(file_name, &contents);
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        disk_manager.split_file(PageId(5), shard_name).unwrap();
+        drop(disk_manager);
+
+        let mut disk_manager = DiskManager::open_sharded(&[file_name, shard_name]).unwrap();
+
+        assert_eq!(disk_manager.page_count(), 10);
+        for i in 0..10 {
+            assert_eq!(
+                disk_manager.read_into_vec(PageId(i)).unwrap(),
+                [i as u8; PAGE_SIZE]
+            );
+        }
+        drop(disk_manager);
+        create_tmp_file(partial_name, &[0; 10]);
+        let err = DiskManager::open_sharded(&[partial_name, shard_name]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let no_paths: [&str; 0] = [];
+        let err = DiskManager::open_sharded(&no_paths).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        remove_file(file_name).unwrap();
+        remove_file(shard_name).unwrap();
+        remove_file(partial_name).unwrap();
+    }
+
+    #[test]
+    fn test_split_file_invalid_boundary() {
+        let file_name = "test_disk_manager_split_file_invalid_boundary.txt";
+        let shard_name = "test_disk_manager_split_file_invalid_boundary_shard.txt";
+        let second_shard_name = "test_disk_manager_split_file_invalid_boundary_shard2.txt";
+        create_tmp_file(file_name, &[0; PAGE_SIZE * 4]);
+
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+
+        for boundary in [0, 5] {
+            let err = disk_manager
+                .split_file(PageId(boundary), shard_name)
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+        disk_manager.split_file(PageId(2), shard_name).unwrap();
+        let err = disk_manager
+            .split_file(PageId(1), second_shard_name)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        disk_manager
+            .split_file(PageId(3), second_shard_name)
+            .unwrap();
+        assert_eq!(
+            std::fs::metadata(shard_name).unwrap().len(),
+            PAGE_SIZE as u64
+        );
+
+        remove_file(file_name).unwrap();
+        remove_file(shard_name).unwrap();
+        remove_file(second_shard_name).unwrap();
+    }
+
+    #[test]
+    fn test_from_backend() {
+        let mut disk_manager = DiskManager::from_backend(Cursor::new(vec![7; PAGE_SIZE])).unwrap();
+        assert_eq!(disk_manager.page_count(), 1);
+
+        let page_id = disk_manager.allocate_page().unwrap();
+        let data: Vec<u8> = (0..PAGE_SIZE).map(|i| (i % 251) as u8).collect();
+        disk_manager.write_page_data(page_id, &data).unwrap();
+        disk_manager.sync().unwrap();
+
+        assert_eq!(page_id, PageId(1));
+        assert_eq!(
+            disk_manager.read_into_vec(PageId(0)).unwrap(),
+            [7; PAGE_SIZE]
+        );
+        assert_eq!(disk_manager.read_into_vec(page_id).unwrap(), data);
+        assert_eq!(disk_manager.heap_file.get_ref().len(), PAGE_SIZE * 2);
+        assert_eq!(disk_manager.to_string(), "<unnamed>: 2 pages of 4096 bytes");
+    }
+
+    // Claims to write but keeps the old bytes.
+    struct DroppingBackend(Cursor<Vec<u8>>);
+
+    impl Read for DroppingBackend {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for DroppingBackend {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for DroppingBackend {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    impl Backend for DroppingBackend {}
+
+    // Counts the reads that reach the underlying cursor.
+    struct CountingBackend {
+        inner: Cursor<Vec<u8>>,
+        reads: u32,
+    }
+
+    impl Read for CountingBackend {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for CountingBackend {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Seek for CountingBackend {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    impl Backend for CountingBackend {}
+
+    // Lets `skips` seeks through, then fails the next `failures` seeks with
+    // `Interrupted`, which `read_exact` and `write_all` don't retry on their
+    // own.
+    struct FlakyBackend {
+        inner: Cursor<Vec<u8>>,
+        skips: u32,
+        failures: u32,
+    }
+
+    impl Read for FlakyBackend {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for FlakyBackend {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Seek for FlakyBackend {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            if self.skips > 0 {
+                self.skips -= 1;
+            } else if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::from(ErrorKind::Interrupted));
+            }
+            self.inner.seek(pos)
+        }
+    }
+
+    impl Backend for FlakyBackend {}
+
+    fn flaky_disk_manager(failures: u32) -> DiskManager<FlakyBackend> {
+        let mut disk_manager = DiskManager::from_backend(FlakyBackend {
+            inner: Cursor::new(vec![0; PAGE_SIZE * 2]),
+            skips: 0,
+            failures: 0,
+        })
+        .unwrap();
+        disk_manager.heap_file.failures = failures;
+        disk_manager
+    }
+
+    #[test]
+    fn test_retry_through_backend() {
+        let mut disk_manager = flaky_disk_manager(2);
+        disk_manager
+            .write_page_data(PageId(0), &[1; PAGE_SIZE])
+            .unwrap();
+        disk_manager.heap_file.failures = 2;
+        disk_manager.write_at(PageId(1), 1, &[2; 3]).unwrap();
+        disk_manager.heap_file.failures = 2;
+        let mut data = vec![0; PAGE_SIZE];
+        disk_manager.read_page_data(PageId(0), &mut data).unwrap();
+        assert_eq!(data, [1; PAGE_SIZE]);
+        disk_manager.heap_file.failures = 2;
+        let mut bufs = vec![[0u8; PAGE_SIZE]; 2];
+        disk_manager
+            .read_pages_gather(&[PageId(0), PageId(1)], &mut bufs)
+            .unwrap();
+        assert_eq!(bufs[0], [1; PAGE_SIZE]);
+        assert_eq!(bufs[1][..5], [0, 2, 2, 2, 0]);
+        // the write's own seek succeeds and the read-back's seeks fail
+        disk_manager.set_verify_writes(true);
+        disk_manager.heap_file.skips = 1;
+        disk_manager.heap_file.failures = 2;
+        disk_manager
+            .write_page_data(PageId(1), &[3; PAGE_SIZE])
+            .unwrap();
+        assert_eq!(disk_manager.heap_file.failures, 0);
+    }
+
+    #[test]
+    fn test_retry_through_backend_gives_up() {
+        let mut disk_manager = flaky_disk_manager(2);
+        disk_manager.set_max_io_attempts(2);
+
+        let err = disk_manager
+            .write_page_data(PageId(0), &[1; PAGE_SIZE])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+        disk_manager.heap_file.failures = 2;
+        let mut data = vec![0; PAGE_SIZE];
+        let err = disk_manager
+            .read_page_data(PageId(0), &mut data)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn test_verify_writes() {
+        let mut disk_manager = DiskManager::from_backend(Cursor::new(vec![0; PAGE_SIZE])).unwrap();
+        disk_manager.set_verify_writes(true);
+
+        disk_manager
+            .write_page_data(PageId(0), &[1; PAGE_SIZE])
+            .unwrap();
+
+        assert_eq!(
+            disk_manager.read_into_vec(PageId(0)).unwrap(),
+            [1; PAGE_SIZE]
+        );
+    }
+
+    #[test]
+    fn test_verify_writes_dropped() {
+        let backend = DroppingBackend(Cursor::new(vec![0; PAGE_SIZE]));
+        let mut disk_manager = DiskManager::from_backend(backend).unwrap();
+
+        disk_manager
+            .write_page_data(PageId(0), &[1; PAGE_SIZE])
+            .unwrap();
+        disk_manager.set_verify_writes(true);
+        let err = disk_manager
+            .write_page_data(PageId(0), &[1; PAGE_SIZE])
+            .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "write verification failed for page 0");
+    }
+
+    #[test]
+    fn test_open_locked() {
+        let file_name = "test_disk_manager_open_locked.txt";
+
+        let disk_manager = DiskManager::open(file_name).unwrap();
+
+        let err = DiskManager::open(file_name).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResourceBusy);
+        let err = DiskManager::open_checked(file_name).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResourceBusy);
+        let err = DiskManager::open_read_only(file_name).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResourceBusy);
+        DiskManager::open_unlocked(file_name).unwrap();
+        drop(disk_manager);
+        DiskManager::open(file_name).unwrap();
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_open_read_only_shared() {
+        let file_name = "test_disk_manager_open_read_only_shared.txt";
+        create_tmp_file(file_name, &[0; PAGE_SIZE]);
+
+        let first = DiskManager::open_read_only(file_name).unwrap();
+        let second = DiskManager::open_read_only(file_name).unwrap();
+
+        let err = DiskManager::open(file_name).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResourceBusy);
+        drop((first, second));
+        DiskManager::open(file_name).unwrap();
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_open_read_only() {
+        let file_name = "test_disk_manager_open_read_only.txt";
+        create_tmp_file(file_name, &[1; PAGE_SIZE]);
+
+        let mut disk_manager = DiskManager::open_read_only(file_name).unwrap();
+        let mut buf = vec![0; PAGE_SIZE];
+        disk_manager.read_page_data(PageId(0), &mut buf).unwrap();
+
+        assert!(disk_manager.is_read_only());
+        assert_eq!(buf, vec![1; PAGE_SIZE]);
+        assert_eq!(disk_manager.next_page_id, 1);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_open_read_only_missing() {
+        let file_name = "test_disk_manager_open_read_only_missing.txt";
+
+        let err = DiskManager::open_read_only(file_name).err().unwrap();
+
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_read_only_rejects_writes() {
+        let file_name = "test_disk_manager_read_only_rejects_writes.txt";
+        create_tmp_file(file_name, &[1; PAGE_SIZE]);
+
+        let mut disk_manager = DiskManager::open_read_only(file_name).unwrap();
+
+        let err = disk_manager
+            .write_page_data(PageId(0), &[2; PAGE_SIZE])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ReadOnlyFilesystem);
+        let err = disk_manager.allocate_page().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ReadOnlyFilesystem);
+        assert_eq!(disk_manager.next_page_id, 1);
+        let err = disk_manager.sync().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ReadOnlyFilesystem);
+
+        remove_file(file_name).unwrap();
+    }
+
+    fn create_tmp_file(file_name: &str, contents: &[u8]) -> File {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .open(file_name)
+            .unwrap();
+        file.write_all(contents).unwrap();
+        file
+    }
+}
+
+#[cfg(all(test, feature = "trace"))]
+mod test_disk_manager_trace {
+    use std::{
+        fs::remove_file,
+        io::Write,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    use super::{DiskManager, PageId, PAGE_SIZE};
+
+    type RecordedSpans = Arc<Mutex<Vec<(&'static str, Option<u64>)>>>;
+
+    // Records the name and `page_id` field of every span created.
+    #[derive(Default)]
+    struct SpanRecorder {
+        next_id: AtomicU64,
+        spans: RecordedSpans,
+    }
+
+    struct PageIdVisitor(Option<u64>);
+
+    impl Visit for PageIdVisitor {
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            if field.name() == "page_id" {
+                self.0 = Some(value);
+            }
+        }
+
+        fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+    }
+
+    impl Subscriber for SpanRecorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+            let mut visitor = PageIdVisitor(None);
+            span.record(&mut visitor);
+            self.spans
+                .lock()
+                .unwrap()
+                .push((span.metadata().name(), visitor.0));
+            span::Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, _span: &span::Id) {}
+
+        fn exit(&self, _span: &span::Id) {}
+    }
+
+    #[test]
+    fn test_read_page_data_span() {
+        let file_name = "test_disk_manager_trace_read_page_data_span.txt";
+        std::fs::File::create(file_name)
+            .unwrap()
+            .write_all(&[1; PAGE_SIZE * 3])
+            .unwrap();
+        let mut disk_manager = DiskManager::open(file_name).unwrap();
+        let recorder = SpanRecorder::default();
+        let spans = Arc::clone(&recorder.spans);
+
+        tracing::subscriber::with_default(recorder, || {
+            let mut data = vec![0; PAGE_SIZE];
+            disk_manager.read_page_data(PageId(2), &mut data).unwrap();
+        });
+
+        assert_eq!(*spans.lock().unwrap(), vec![("read_page_data", Some(2))]);
+
+        remove_file(file_name).unwrap();
     }
 }
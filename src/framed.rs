@@ -0,0 +1,389 @@
+use std::{fs::File, io};
+
+use crate::disk::{Backend, DiskManager, PageId, PAGE_SIZE};
+
+const LEN_SIZE: usize = 4;
+
+/// Log-structured record storage: each record is a little-endian u32 length
+/// followed by its bytes, packed back to back across pages with no page
+/// headers, so the file can be read with external tools. A zero length marks
+/// the end of the log. Records can only be appended, never updated in place.
+/// A last frame that runs past the end of the file was torn by a crash during
+/// `append`; opening the log drops it and zeroes what it left in the tail page.
+pub struct FramedHeapFile<B: Backend = File> {
+    disk: DiskManager<B>,
+    end: u64,
+    tail: Vec<u8>,
+}
+
+impl<B: Backend> FramedHeapFile<B> {
+    pub fn new(mut disk: DiskManager<B>) -> io::Result<Self> {
+        let end = find_end(&mut disk)?;
+        let tail_page_id = PageId(end / PAGE_SIZE as u64);
+        let tail = if tail_page_id.to_u64() < disk.page_count() {
+            let mut tail = disk.read_into_vec(tail_page_id)?;
+            let offset_in_page = end as usize % PAGE_SIZE;
+            if tail[offset_in_page..].iter().any(|&b| b != 0) {
+                tail[offset_in_page..].fill(0);
+                disk.write_page_data(tail_page_id, &tail)?;
+            }
+            tail
+        } else {
+            vec![0; PAGE_SIZE]
+        };
+        Ok(Self { disk, end, tail })
+    }
+
+    /// Returns the byte offset of the new frame. On error the log is left as it
+    /// was before the call, though the pages it spans may stay allocated.
+    pub fn append(&mut self, record: &[u8]) -> io::Result<u64> {
+        if record.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "empty records cannot be framed",
+            ));
+        }
+        let len = u32::try_from(record.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("record of {} bytes is too large to frame", record.len()),
+            )
+        })?;
+        let mut frame = Vec::with_capacity(LEN_SIZE + record.len());
+        frame.extend_from_slice(&len.to_le_bytes());
+        frame.extend_from_slice(record);
+        let offset = self.end;
+        let tail = self.tail.clone();
+        if let Err(err) = self.write_bytes(&frame) {
+            self.end = offset;
+            self.tail = tail;
+            return Err(err);
+        }
+        Ok(offset)
+    }
+
+    pub fn scan(&mut self) -> FramedScan<'_, B> {
+        FramedScan {
+            reader: FrameReader::new(&mut self.disk),
+            pos: 0,
+            end: self.end,
+        }
+    }
+
+    pub fn end_offset(&self) -> u64 {
+        self.end
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.disk.sync()
+    }
+
+    fn write_bytes(&mut self, mut bytes: &[u8]) -> io::Result<()> {
+        while !bytes.is_empty() {
+            let page_id = PageId(self.end / PAGE_SIZE as u64);
+            let offset_in_page = self.end as usize % PAGE_SIZE;
+            let n = bytes.len().min(PAGE_SIZE - offset_in_page);
+            self.tail[offset_in_page..offset_in_page + n].copy_from_slice(&bytes[..n]);
+            while self.disk.page_count() <= page_id.to_u64() {
+                self.disk.allocate_page()?;
+            }
+            self.disk.write_page_data(page_id, &self.tail)?;
+            self.end += n as u64;
+            bytes = &bytes[n..];
+            if self.end.is_multiple_of(PAGE_SIZE as u64) {
+                self.tail.fill(0);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct FramedScan<'a, B: Backend = File> {
+    reader: FrameReader<'a, B>,
+    pos: u64,
+    end: u64,
+}
+
+impl<B: Backend> Iterator for FramedScan<'_, B> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.end {
+            return None;
+        }
+        let result = self.reader.read_frame(self.pos).and_then(|frame| {
+            frame.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "frame log ended early"))
+        });
+        match result {
+            Ok(record) => {
+                self.pos += (LEN_SIZE + record.len()) as u64;
+                Some(Ok(record))
+            }
+            Err(err) => {
+                self.pos = self.end;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+struct FrameReader<'a, B: Backend> {
+    disk: &'a mut DiskManager<B>,
+    page_id: Option<PageId>,
+    page: Vec<u8>,
+}
+
+impl<'a, B: Backend> FrameReader<'a, B> {
+    fn new(disk: &'a mut DiskManager<B>) -> Self {
+        Self {
+            disk,
+            page_id: None,
+            page: vec![0; PAGE_SIZE],
+        }
+    }
+
+    // Returns `None` at the end of the log.
+    fn read_frame(&mut self, pos: u64) -> io::Result<Option<Vec<u8>>> {
+        // A complete frame always spans pages that were written in full, so
+        // fewer bytes than a length prefix before the end of the file can only
+        // be padding, and a frame running past it was torn.
+        let file_len = self.disk.page_count() * PAGE_SIZE as u64;
+        if file_len.saturating_sub(pos) < LEN_SIZE as u64 {
+            return Ok(None);
+        }
+        let mut len = [0; LEN_SIZE];
+        self.read_bytes(pos, &mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len == 0 || file_len - pos - (LEN_SIZE as u64) < len as u64 {
+            return Ok(None);
+        }
+        let mut record = vec![0; len];
+        self.read_bytes(pos + LEN_SIZE as u64, &mut record)?;
+        Ok(Some(record))
+    }
+
+    fn read_bytes(&mut self, mut pos: u64, mut out: &mut [u8]) -> io::Result<()> {
+        while !out.is_empty() {
+            let page_id = PageId(pos / PAGE_SIZE as u64);
+            if self.page_id != Some(page_id) {
+                self.disk.read_page_data(page_id, &mut self.page)?;
+                self.page_id = Some(page_id);
+            }
+            let offset_in_page = pos as usize % PAGE_SIZE;
+            let n = out.len().min(PAGE_SIZE - offset_in_page);
+            out[..n].copy_from_slice(&self.page[offset_in_page..offset_in_page + n]);
+            pos += n as u64;
+            out = &mut out[n..];
+        }
+        Ok(())
+    }
+}
+
+fn find_end<B: Backend>(disk: &mut DiskManager<B>) -> io::Result<u64> {
+    let mut reader = FrameReader::new(disk);
+    let mut pos = 0;
+    while let Some(record) = reader.read_frame(pos)? {
+        pos += (LEN_SIZE + record.len()) as u64;
+    }
+    Ok(pos)
+}
+
+#[cfg(test)]
+mod test_framed_heap_file {
+    use std::{
+        cell::Cell,
+        fs::{remove_file, OpenOptions},
+        io::{self, Cursor, ErrorKind, Read, Seek, Write},
+        rc::Rc,
+    };
+
+    use crate::disk::{Backend, DiskManager, PageId, PAGE_SIZE};
+
+    use super::FramedHeapFile;
+
+    // Fails every write once `writes_left` runs out.
+    struct FailingBackend {
+        inner: Cursor<Vec<u8>>,
+        writes_left: Rc<Cell<usize>>,
+    }
+
+    impl Read for FailingBackend {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for FailingBackend {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.writes_left.get() == 0 {
+                return Err(io::Error::other("injected write failure"));
+            }
+            self.writes_left.set(self.writes_left.get() - 1);
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Seek for FailingBackend {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    impl Backend for FailingBackend {}
+
+    #[test]
+    fn test_append_scan() {
+        let file_name = "test_framed_heap_file_append_scan.txt";
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+        let records: Vec<Vec<u8>> = vec![b"Hello".to_vec(), b", ".to_vec(), b"World!".to_vec()];
+
+        let offsets: Vec<u64> = records
+            .iter()
+            .map(|record| heap.append(record).unwrap())
+            .collect();
+
+        assert_eq!(offsets, vec![0, 9, 15]);
+        assert_eq!(heap.end_offset(), 25);
+        let scanned: Vec<Vec<u8>> = heap.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned, records);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_record_spanning_pages() {
+        let file_name = "test_framed_heap_file_record_spanning_pages.txt";
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+        let first = vec![1; PAGE_SIZE - 10];
+        let spanning: Vec<u8> = (0..100).collect();
+        let large: Vec<u8> = (0..PAGE_SIZE * 2).map(|i| (i % 251) as u8).collect();
+
+        heap.append(&first).unwrap();
+        heap.append(&spanning).unwrap();
+        heap.append(&large).unwrap();
+
+        let scanned: Vec<Vec<u8>> = heap.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned, vec![first, spanning, large]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_reopen_near_page_end() {
+        let file_name = "test_framed_heap_file_reopen_near_page_end.txt";
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+        // leaves 2 bytes of the first page, too few for a length prefix
+        heap.append(&[1; PAGE_SIZE - 6]).unwrap();
+        drop(heap);
+
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+        assert_eq!(heap.end_offset(), PAGE_SIZE as u64 - 2);
+        heap.append(&[2; 5]).unwrap();
+
+        let scanned: Vec<Vec<u8>> = heap.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned, vec![vec![1; PAGE_SIZE - 6], vec![2; 5]]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_reopen() {
+        let file_name = "test_framed_heap_file_reopen.txt";
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+        heap.append(&[1; 3000]).unwrap();
+        heap.append(&[2; 3000]).unwrap();
+        heap.sync().unwrap();
+        let end = heap.end_offset();
+        drop(heap);
+
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+        assert_eq!(heap.end_offset(), end);
+        heap.append(&[3; 10]).unwrap();
+
+        let scanned: Vec<Vec<u8>> = heap.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned, vec![vec![1; 3000], vec![2; 3000], vec![3; 10]]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_raw_framing() {
+        let file_name = "test_framed_heap_file_raw_framing.txt";
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+        heap.append(b"abc").unwrap();
+        drop(heap);
+
+        let mut disk = DiskManager::open(file_name).unwrap();
+        let page = disk.read_into_vec(PageId(0)).unwrap();
+        assert_eq!(&page[..7], &[3, 0, 0, 0, b'a', b'b', b'c']);
+        assert!(page[7..].iter().all(|&b| b == 0));
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_reopen_torn_last_frame() {
+        let file_name = "test_framed_heap_file_reopen_torn_last_frame.txt";
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+        let first_end = PAGE_SIZE as u64 - 100;
+        heap.append(&[1; PAGE_SIZE - 104]).unwrap();
+        heap.append(&[2; PAGE_SIZE * 2]).unwrap();
+        drop(heap);
+        // a crash before the last page of the second frame reached the disk
+        OpenOptions::new()
+            .write(true)
+            .open(file_name)
+            .unwrap()
+            .set_len(PAGE_SIZE as u64 * 2)
+            .unwrap();
+
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+        assert_eq!(heap.end_offset(), first_end);
+        heap.append(&[3; 10]).unwrap();
+        drop(heap);
+
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+        let scanned: Vec<Vec<u8>> = heap.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned, vec![vec![1; PAGE_SIZE - 104], vec![3; 10]]);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_append_failure_rolls_back() {
+        let writes_left = Rc::new(Cell::new(usize::MAX));
+        let backend = FailingBackend {
+            inner: Cursor::new(vec![]),
+            writes_left: writes_left.clone(),
+        };
+        let mut heap = FramedHeapFile::new(DiskManager::from_backend(backend).unwrap()).unwrap();
+        heap.append(b"abc").unwrap();
+
+        // the first page of the frame is written, the second is not
+        writes_left.set(1);
+        assert!(heap.append(&[1; PAGE_SIZE]).is_err());
+        assert_eq!(heap.end_offset(), 7);
+
+        writes_left.set(usize::MAX);
+        heap.append(b"de").unwrap();
+        let scanned: Vec<Vec<u8>> = heap.scan().map(Result::unwrap).collect();
+        assert_eq!(scanned, vec![b"abc".to_vec(), b"de".to_vec()]);
+    }
+
+    #[test]
+    fn test_append_empty() {
+        let file_name = "test_framed_heap_file_append_empty.txt";
+        let mut heap = FramedHeapFile::new(DiskManager::open(file_name).unwrap()).unwrap();
+
+        let err = heap.append(&[]).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(heap.end_offset(), 0);
+
+        remove_file(file_name).unwrap();
+    }
+}
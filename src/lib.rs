@@ -1,2 +1,9 @@
+#[cfg(feature = "tokio")]
+pub mod async_disk;
+pub mod benchmark;
 pub mod buffer;
+pub mod dictionary;
 pub mod disk;
+pub mod framed;
+pub mod meta;
+pub mod temp;
@@ -0,0 +1,275 @@
+use std::io;
+
+use crate::disk::{fnv1a, Backend, DiskManager, PageId, PAGE_SIZE};
+
+pub const META_COUNTERS: usize = 8;
+
+const SLOT_SIZE: usize = PAGE_SIZE / 2;
+// sequence number, counters, checksum
+const SLOT_LEN: usize = 8 * (META_COUNTERS + 2);
+
+/// Database-wide u64 counters kept in one page that survive a crash at any
+/// point of an update. The page holds two copies, each stamped with a sequence
+/// number and checksummed; an update only ever overwrites the older copy, so
+/// a torn write leaves the newer one intact and it wins on the next load.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaPage {
+    page_id: PageId,
+    seq: u64,
+    counters: [u64; META_COUNTERS],
+}
+
+impl MetaPage {
+    /// Allocates pages up to `page_id` if the heap is shorter than that and
+    /// starts with all counters at zero.
+    pub fn open<B: Backend>(disk: &mut DiskManager<B>, page_id: PageId) -> io::Result<Self> {
+        if page_id.to_u64() >= disk.page_count() {
+            while disk.page_count() <= page_id.to_u64() {
+                disk.allocate_page()?;
+            }
+            disk.write_page_data(page_id, &[0; PAGE_SIZE])?;
+            disk.sync()?;
+        }
+        let page = disk.read_into_vec(page_id)?;
+        let newest = page
+            .chunks(SLOT_SIZE)
+            .filter_map(decode_slot)
+            .max_by_key(|&(seq, _)| seq);
+        let (seq, counters) = newest.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("both copies of meta page {} are corrupt", page_id.to_u64()),
+            )
+        })?;
+        Ok(Self {
+            page_id,
+            seq,
+            counters,
+        })
+    }
+
+    /// Returns `None` for a counter index of `META_COUNTERS` or more.
+    pub fn get(&self, counter: usize) -> Option<u64> {
+        self.counters.get(counter).copied()
+    }
+
+    /// Durable once this returns. On error the on-disk value is either the
+    /// old or the new one, and this handle keeps the old value. A counter
+    /// index of `META_COUNTERS` or more fails with `InvalidInput` before
+    /// anything is written.
+    pub fn set<B: Backend>(
+        &mut self,
+        disk: &mut DiskManager<B>,
+        counter: usize,
+        value: u64,
+    ) -> io::Result<()> {
+        if counter >= META_COUNTERS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("meta page has no counter {counter}"),
+            ));
+        }
+        let mut counters = self.counters;
+        counters[counter] = value;
+        let seq = self.seq + 1;
+        let slot = encode_slot(seq, &counters);
+        disk.write_at(self.page_id, slot_offset(seq), &slot)?;
+        disk.sync()?;
+        self.seq = seq;
+        self.counters = counters;
+        Ok(())
+    }
+}
+
+// Sequence numbers alternate between the two copies.
+fn slot_offset(seq: u64) -> usize {
+    (seq % 2) as usize * SLOT_SIZE
+}
+
+fn encode_slot(seq: u64, counters: &[u64; META_COUNTERS]) -> Vec<u8> {
+    let mut slot = Vec::with_capacity(SLOT_LEN);
+    slot.extend_from_slice(&seq.to_le_bytes());
+    for counter in counters {
+        slot.extend_from_slice(&counter.to_le_bytes());
+    }
+    let checksum = fnv1a(&slot);
+    slot.extend_from_slice(&checksum.to_le_bytes());
+    slot
+}
+
+fn decode_slot(slot: &[u8]) -> Option<(u64, [u64; META_COUNTERS])> {
+    let slot = &slot[..SLOT_LEN];
+    // a copy that was never written holds the initial, all-zero counters
+    if slot.iter().all(|&b| b == 0) {
+        return Some((0, [0; META_COUNTERS]));
+    }
+    let (body, checksum) = slot.split_at(SLOT_LEN - 8);
+    if fnv1a(body) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+        return None;
+    }
+    let seq = u64::from_le_bytes(body[..8].try_into().unwrap());
+    let mut counters = [0; META_COUNTERS];
+    for (counter, bytes) in counters.iter_mut().zip(body[8..].chunks(8)) {
+        *counter = u64::from_le_bytes(bytes.try_into().unwrap());
+    }
+    Some((seq, counters))
+}
+
+#[cfg(test)]
+mod test_meta_page {
+    use std::{
+        cell::{Cell, RefCell},
+        fs::remove_file,
+        io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom, Write},
+        rc::Rc,
+    };
+
+    use crate::disk::{Backend, DiskManager, PageId};
+
+    use super::{MetaPage, META_COUNTERS, SLOT_SIZE};
+
+    const MAX_TXN_ID: usize = 0;
+    const NEXT_SEQUENCE_BLOCK: usize = 1;
+
+    // In-memory storage that outlives the manager using it and can be told
+    // to "crash" partway through a write, keeping only the first bytes.
+    #[derive(Clone, Default)]
+    struct CrashingBackend {
+        data: Rc<RefCell<Vec<u8>>>,
+        pos: u64,
+        crash_after: Rc<Cell<Option<usize>>>,
+    }
+
+    impl Read for CrashingBackend {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let data = self.data.borrow();
+            let start = (self.pos as usize).min(data.len());
+            let n = buf.len().min(data.len() - start);
+            buf[..n].copy_from_slice(&data[start..start + n]);
+            self.pos += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Write for CrashingBackend {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let (buf, crashed) = match self.crash_after.take() {
+                Some(n) => (&buf[..n.min(buf.len())], true),
+                None => (buf, false),
+            };
+            let mut data = self.data.borrow_mut();
+            let end = self.pos as usize + buf.len();
+            if data.len() < end {
+                data.resize(end, 0);
+            }
+            data[self.pos as usize..end].copy_from_slice(buf);
+            self.pos = end as u64;
+            if crashed {
+                return Err(io::Error::other("crashed mid-write"));
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for CrashingBackend {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.pos = match pos {
+                SeekFrom::Start(offset) => offset,
+                SeekFrom::End(offset) => (self.data.borrow().len() as i64 + offset) as u64,
+                SeekFrom::Current(offset) => (self.pos as i64 + offset) as u64,
+            };
+            Ok(self.pos)
+        }
+    }
+
+    impl Backend for CrashingBackend {}
+
+    #[test]
+    fn test_set_reopen() {
+        let file_name = "test_meta_page_set_reopen.txt";
+        let mut disk = DiskManager::open(file_name).unwrap();
+        let mut meta = MetaPage::open(&mut disk, PageId(0)).unwrap();
+        assert_eq!(meta.get(MAX_TXN_ID), Some(0));
+
+        for txn_id in 1..=5 {
+            meta.set(&mut disk, MAX_TXN_ID, txn_id).unwrap();
+        }
+        meta.set(&mut disk, NEXT_SEQUENCE_BLOCK, 4096).unwrap();
+        drop(disk);
+
+        let mut disk = DiskManager::open(file_name).unwrap();
+        let meta = MetaPage::open(&mut disk, PageId(0)).unwrap();
+        assert_eq!(meta.get(MAX_TXN_ID), Some(5));
+        assert_eq!(meta.get(NEXT_SEQUENCE_BLOCK), Some(4096));
+        assert_eq!(disk.page_count(), 1);
+
+        remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_counter_out_of_range() {
+        let mut disk = DiskManager::from_backend(Cursor::new(vec![])).unwrap();
+        let mut meta = MetaPage::open(&mut disk, PageId(0)).unwrap();
+        meta.set(&mut disk, MAX_TXN_ID, 7).unwrap();
+        let page = disk.read_into_vec(PageId(0)).unwrap();
+
+        let err = meta.set(&mut disk, META_COUNTERS, 1).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(meta.get(META_COUNTERS), None);
+        assert_eq!(meta.get(MAX_TXN_ID), Some(7));
+        assert_eq!(disk.read_into_vec(PageId(0)).unwrap(), page);
+    }
+
+    #[test]
+    fn test_crash_mid_update() {
+        let backend = CrashingBackend::default();
+        let mut disk = DiskManager::from_backend(backend.clone()).unwrap();
+        let mut meta = MetaPage::open(&mut disk, PageId(2)).unwrap();
+        meta.set(&mut disk, MAX_TXN_ID, 41).unwrap();
+        meta.set(&mut disk, MAX_TXN_ID, 42).unwrap();
+
+        // only part of the next copy reaches the disk
+        backend.crash_after.set(Some(12));
+        meta.set(&mut disk, MAX_TXN_ID, 43).unwrap_err();
+        assert_eq!(meta.get(MAX_TXN_ID), Some(42));
+        drop(disk);
+
+        let mut disk = DiskManager::from_backend(backend.clone()).unwrap();
+        let meta = MetaPage::open(&mut disk, PageId(2)).unwrap();
+        assert_eq!(meta.get(MAX_TXN_ID), Some(42));
+        assert_eq!(disk.page_count(), 3);
+    }
+
+    #[test]
+    fn test_crash_mid_first_update() {
+        let backend = CrashingBackend::default();
+        let mut disk = DiskManager::from_backend(backend.clone()).unwrap();
+        let mut meta = MetaPage::open(&mut disk, PageId(0)).unwrap();
+
+        backend.crash_after.set(Some(12));
+        meta.set(&mut disk, MAX_TXN_ID, 1).unwrap_err();
+
+        let meta = MetaPage::open(&mut disk, PageId(0)).unwrap();
+        assert_eq!(meta.get(MAX_TXN_ID), Some(0));
+    }
+
+    #[test]
+    fn test_both_copies_corrupt() {
+        let backend = CrashingBackend::default();
+        let mut disk = DiskManager::from_backend(backend.clone()).unwrap();
+        let mut meta = MetaPage::open(&mut disk, PageId(0)).unwrap();
+        meta.set(&mut disk, MAX_TXN_ID, 1).unwrap();
+        meta.set(&mut disk, MAX_TXN_ID, 2).unwrap();
+        backend.data.borrow_mut()[4] ^= 1;
+        backend.data.borrow_mut()[SLOT_SIZE + 4] ^= 1;
+
+        let err = MetaPage::open(&mut disk, PageId(0)).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
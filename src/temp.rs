@@ -0,0 +1,179 @@
+use std::{
+    fs::{create_dir_all, read_dir, remove_file, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::disk::{lock_heap_file, DiskManager};
+
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Hands out scratch heap files for spilling and removes them again on
+/// `cleanup_all` or drop, so an abandoned query doesn't leak temp space.
+/// Files left behind by a process that died before cleaning up are removed by
+/// the next `new` on the same directory; files still locked by a live manager
+/// are kept.
+#[derive(Debug)]
+pub struct TempManager {
+    dir: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+impl TempManager {
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        create_dir_all(&dir)?;
+        for entry in read_dir(&dir)? {
+            let path = entry?.path();
+            let is_temp_file = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("shelly-") && name.ends_with(".tmp"));
+            if !is_temp_file {
+                continue;
+            }
+            match DiskManager::open(&path) {
+                Ok(disk) => drop(disk),
+                Err(err) if err.kind() == io::ErrorKind::ResourceBusy => continue,
+                Err(err) => return Err(err),
+            }
+            match remove_file(&path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Self { dir, files: vec![] })
+    }
+
+    pub fn create(&mut self) -> io::Result<DiskManager> {
+        let temp_id = NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed);
+        let path = self
+            .dir
+            .join(format!("shelly-{}-{}.tmp", process::id(), temp_id));
+        // a stale file under a reused pid must not be handed out again
+        let heap_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let locked = lock_heap_file(&heap_file, &path, false);
+        self.files.push(path);
+        locked?;
+        DiskManager::new(heap_file)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn cleanup_all(&mut self) -> io::Result<()> {
+        while let Some(path) = self.files.pop() {
+            match remove_file(&path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    self.files.push(path);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempManager {
+    fn drop(&mut self) {
+        let _ = self.cleanup_all();
+    }
+}
+
+#[cfg(test)]
+mod test_temp_manager {
+    use std::{
+        fs::{remove_dir, remove_file, write},
+        path::Path,
+    };
+
+    use crate::disk::{PageId, PAGE_SIZE};
+
+    use super::TempManager;
+
+    #[test]
+    fn test_create() {
+        let dir = "test_temp_manager_create";
+        let mut temp = TempManager::new(dir).unwrap();
+
+        let mut disk = temp.create().unwrap();
+        let page_id = disk.allocate_page().unwrap();
+        disk.write_page_data(page_id, &[1; PAGE_SIZE]).unwrap();
+
+        assert_eq!(disk.read_into_vec(PageId(0)).unwrap(), vec![1; PAGE_SIZE]);
+        assert_eq!(temp.files().len(), 1);
+        assert!(temp.files()[0].starts_with(dir));
+        assert!(temp.files()[0].exists());
+
+        drop(temp);
+        remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_new_removes_leftovers() {
+        let dir = "test_temp_manager_new_removes_leftovers";
+        let mut live = TempManager::new(dir).unwrap();
+        let _disk = live.create().unwrap();
+        let stale = format!("{dir}/shelly-0-0.tmp");
+        let other = format!("{dir}/other.tmp");
+        write(&stale, [1; PAGE_SIZE]).unwrap();
+        write(&other, [1; PAGE_SIZE]).unwrap();
+
+        let temp = TempManager::new(dir).unwrap();
+
+        assert!(!Path::new(&stale).exists());
+        assert!(Path::new(&other).exists());
+        assert!(live.files()[0].exists());
+
+        drop(temp);
+        drop(live);
+        remove_file(other).unwrap();
+        remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_cleanup_all() {
+        let dir = "test_temp_manager_cleanup_all";
+        let mut temp = TempManager::new(dir).unwrap();
+        temp.create().unwrap();
+        temp.create().unwrap();
+        let files = temp.files().to_vec();
+
+        temp.cleanup_all().unwrap();
+
+        assert!(temp.files().is_empty());
+        assert!(files.iter().all(|path| !path.exists()));
+
+        drop(temp);
+        remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_drop() {
+        let dir = "test_temp_manager_drop";
+        let mut temp = TempManager::new(dir).unwrap();
+        let disk = temp.create().unwrap();
+        let files = temp.files().to_vec();
+
+        drop(temp);
+        drop(disk);
+
+        assert!(files.iter().all(|path| !path.exists()));
+
+        remove_dir(dir).unwrap();
+    }
+}